@@ -4,6 +4,8 @@ pub mod docs_cli;
 pub mod docs_database;
 #[path = "routes/docs/deployment.rs"]
 pub mod docs_deployment;
+#[path = "routes/docs/forms.rs"]
+pub mod docs_forms;
 #[path = "routes/docs/index.rs"]
 pub mod docs_index;
 #[path = "routes/docs/installation.rs"]
@@ -16,12 +18,16 @@ pub mod docs_layout;
 pub mod docs_layouts;
 #[path = "routes/docs/project_structure.rs"]
 pub mod docs_project_structure;
+#[path = "routes/docs/realtime.rs"]
+pub mod docs_realtime;
 #[path = "routes/docs/request.rs"]
 pub mod docs_request;
 #[path = "routes/docs/response.rs"]
 pub mod docs_response;
 #[path = "routes/docs/routing.rs"]
 pub mod docs_routing;
+#[path = "routes/docs/sessions.rs"]
+pub mod docs_sessions;
 #[path = "routes/docs/static_assets.rs"]
 pub mod docs_static_assets;
 #[path = "routes/docs/tailwind.rs"]
@@ -45,7 +45,42 @@ pub async fn get(req: Req, res: Res) -> Res {
 
         (code_block_with_filename("rejoice build --release", "bash", None))
 
-        p { "This creates an optimized binary and compiled assets." }
+        p { "This creates an optimized binary and compiled assets, along with precompressed " code { ".br" } " and " code { ".gz" } " copies of the assets." }
+
+        h2 { "Compression" }
+
+        p { "HTML, JSON and other text responses are compressed with brotli or gzip for clients that accept it. Streamed pages are sent uncompressed so each chunk reaches the browser right away." }
+
+        p { "If your reverse proxy already compresses responses, turn it off in the app:" }
+
+        (code_block_with_filename("let app = App::new(port, create_router()).compression(false);", "rust", None))
+
+        h2 { "Cross-Origin Requests" }
+
+        p {
+            "Browsers only let scripts on other sites call your app if it allows their origin. In development every "
+            "origin is allowed; in production none are until you set a policy with " code { "App::cors" } ":"
+        }
+
+        (code_block_with_filename(r#"use rejoice::cors::CorsConfig;
+
+let app = App::new(port, create_router()).cors(
+    CorsConfig::default()
+        .allow_origin("https://app.example.com")
+        .allow_credentials(true)
+        .expose_headers(["x-total-count"])
+        .route("/api/public", CorsConfig::permissive())
+        .route("/admin", CorsConfig::default()),
+);"#, "rust", None))
+
+        p {
+            code { "allow_credentials" } " lets those origins send cookies, and requires listing the origins; "
+            "combining it with any origin panics at startup. " code { "route" } " overrides the policy for a path "
+            "prefix, with the longest match winning: " code { "CorsConfig::permissive()" } " allows any origin, and "
+            code { "CorsConfig::default()" } " allows none. Methods default to GET, HEAD, POST, PUT, PATCH and DELETE, "
+            "and any request header is allowed; narrow them with " code { "allow_methods" } " and "
+            code { "allow_headers" } "."
+        }
 
         h2 { "Required Files" }
 
@@ -87,14 +122,73 @@ cd deploy
 
         h3 { "Runtime Variables" }
 
-        p { "For runtime configuration, use " code { "std::env" } ":" }
+        p { "For other runtime configuration, use " code { "std::env" } ":" }
+
+        (code_block_with_filename(r#"let workers: usize = std::env::var("WORKERS")
+    .ok()
+    .and_then(|v| v.parse().ok())
+    .unwrap_or(4);"#, "rust", None))
 
-        (code_block_with_filename(r#"let port: u16 = std::env::var("PORT")
-    .unwrap_or_else(|_| "8080".to_string())
-    .parse()
-    .unwrap();
+        h2 { "Listening Address" }
 
-let app = App::new(port, create_router());"#, "rust", None))
+        p {
+            "Apps listen on " code { "127.0.0.1" } ", which only accepts connections from the same machine. "
+            "That's right behind a reverse proxy on the same host, but inside Docker or on a LAN you need "
+            code { "0.0.0.0" } " (or " code { "::" } " to accept IPv6 too)."
+        }
+
+        p {
+            code { "App::from_env" } " reads the address from the " code { "HOST" } " and " code { "PORT" }
+            " environment variables, defaulting to " code { "127.0.0.1:8080" } ":"
+        }
+
+        (code_block_with_filename("let app = App::from_env(create_router());", "rust", None))
+
+        (code_block_with_filename("HOST=0.0.0.0 PORT=3000 ./my-app", "bash", None))
+
+        p {
+            "Stateful apps use " code { "bind_env" } ", which applies the same variables to an existing app:"
+        }
+
+        (code_block_with_filename("let app = App::with_state(8080, create_router(), state).bind_env();", "rust", None))
+
+        p {
+            "The address can also be set in code with " code { "host" } ", " code { "port" } " or " code { "bind" } ":"
+        }
+
+        (code_block_with_filename(r#"let app = App::new(8080, create_router()).host("0.0.0.0");
+let app = App::new(8080, create_router()).bind("[::1]:3000".parse().unwrap());"#, "rust", None))
+
+        p {
+            "If the address can't be bound, for example because another process is using the port, "
+            code { "run" } " prints the error and exits with status 1. Use " code { "try_run" }
+            " to handle the error yourself."
+        }
+
+        h2 { "Graceful Shutdown" }
+
+        p {
+            "On SIGTERM (sent by systemd, Docker and most process managers) or Ctrl+C, the server stops "
+            "accepting connections and waits for in-flight requests to finish before exiting. Connections "
+            "still open after 30 seconds, such as event streams, are closed. Change the limit with "
+            code { "shutdown_timeout" } ":"
+        }
+
+        (code_block_with_filename("let app = App::new(8080, create_router()).shutdown_timeout(Duration::from_secs(10));", "rust", None))
+
+        p {
+            "Use " code { "on_shutdown" } " to clean up once requests have drained, e.g. to close a database "
+            "pool or flush queued jobs. Hooks run in the order they were added:"
+        }
+
+        (code_block_with_filename(r#"let app = App::with_state(8080, create_router(), state)
+    .on_shutdown(move || async move { pool.close().await });"#, "rust", None))
+
+        p {
+            "Make sure your process manager waits at least as long as the shutdown timeout before killing "
+            "the app. Docker's default is 10 seconds (" code { "docker stop --time" } "), and systemd's is "
+            "90 seconds (" code { "TimeoutStopSec" } ")."
+        }
 
         h2 { "Database" }
 
@@ -125,6 +219,67 @@ let app = App::new(port, create_router());"#, "rust", None))
     }
 }"#, "nginx", None))
 
+        p {
+            "Trust the proxy so " code { "req.client_ip()" } " and " code { "req.is_secure()" }
+            " reflect the real client:"
+        }
+
+        (code_block_with_filename(r#"let app = App::new(port, create_router())
+    .trusted_proxies(["127.0.0.1"]);"#, "rust", None))
+
+        h3 { "Unix Sockets" }
+
+        p {
+            "When the proxy runs on the same host, the app can listen on a Unix socket instead of a TCP port:"
+        }
+
+        (code_block_with_filename(r#"let app = App::new(8080, create_router())
+    .unix_socket("/run/my-app/app.sock")
+    .unix_socket_mode(0o660)
+    .trusted_proxies(["unix"]);"#, "rust", None))
+
+        p {
+            code { "unix_socket_mode" } " sets the file's permissions so the proxy can connect, and "
+            code { "\"unix\"" } " trusts forwarding headers from Unix socket peers. A socket file left over "
+            "from a previous run is replaced, but the app refuses to start if another process is still "
+            "listening on it. The file is removed on shutdown."
+        }
+
+        p { "Point nginx at the socket:" }
+
+        (code_block_with_filename("proxy_pass http://unix:/run/my-app/app.sock;", "nginx", None))
+
+        h2 { "HTTPS Without a Proxy" }
+
+        p { "Small internal tools can serve HTTPS directly. Enable the " code { "tls" } " feature:" }
+
+        (code_block_with_filename(r#"[dependencies]
+rejoice = { version = "0.11", features = ["tls"] }"#, "toml", None))
+
+        p { "Then pass the PEM certificate chain and private key to " code { "App::tls" } ":" }
+
+        (code_block_with_filename(r#"let app = App::new(443, create_router())
+    .host("0.0.0.0")
+    .tls("/etc/letsencrypt/live/example.com/fullchain.pem", "/etc/letsencrypt/live/example.com/privkey.pem")
+    .redirect_http(80);"#, "rust", None))
+
+        p {
+            "The files are watched, and renewed certificates are picked up without a restart. If a renewal "
+            "leaves the files unreadable, the app keeps the current certificate and prints a warning. "
+            code { "redirect_http(80)" } " also listens for plain HTTP on port 80 and answers every request "
+            "with a 308 redirect to the HTTPS URL."
+        }
+
+        p { "To try it locally, create a self-signed certificate:" }
+
+        (code_block_with_filename(r#"openssl req -x509 -newkey rsa:2048 -nodes -days 365 \
+  -keyout key.pem -out cert.pem -subj /CN=localhost"#, "bash", None))
+
+        p {
+            "Browsers will warn about the self-signed certificate; "
+            code { "curl -k https://localhost:8443" } " skips the check."
+        }
+
         h2 { "Systemd Service" }
 
         p { "Create a systemd service for auto-restart:" }
@@ -150,6 +305,34 @@ WantedBy=multi-user.target"#, "ini", Some("/etc/systemd/system/my-app.service"))
         (code_block_with_filename(r#"sudo systemctl enable my-app
 sudo systemctl start my-app"#, "bash", None))
 
+        h3 { "Socket Activation" }
+
+        p {
+            "With systemd socket activation, systemd owns the listening socket and passes it to the app "
+            "through " code { "LISTEN_FDS" } ". Connections that arrive while the app restarts wait in the "
+            "socket's queue instead of being refused. The app uses the inherited socket automatically, "
+            "whether it's TCP or a Unix socket, and ignores its configured address."
+        }
+
+        (code_block_with_filename(r#"[Socket]
+ListenStream=/run/my-app.sock
+SocketMode=0660
+SocketGroup=www-data
+
+[Install]
+WantedBy=sockets.target"#, "ini", Some("/etc/systemd/system/my-app.socket")))
+
+        p {
+            "Add " code { "Requires=my-app.socket" } " to the " code { "[Unit]" }
+            " section of the service, then enable the socket:"
+        }
+
+        (code_block_with_filename("sudo systemctl enable --now my-app.socket", "bash", None))
+
+        p {
+            "Test it locally with " code { "systemd-socket-activate -l 8080 ./target/release/my-app" } "."
+        }
+
         h2 { "Docker" }
 
         p { "Example " code { "Dockerfile" } ":" }
@@ -177,6 +360,7 @@ CMD ["./my-app"]"#, "dockerfile", Some("Dockerfile")))
             li { "Copy " code { "public/" } " directory" }
             li { "Set up environment variables" }
             li { "Configure reverse proxy" }
+            li { "Set a CORS policy with " code { "App::cors" } " if other sites call your app" }
             li { "Set up process manager (systemd, Docker, etc.)" }
             li { "Configure firewall (only expose 80/443)" }
         }
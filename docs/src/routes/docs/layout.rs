@@ -102,6 +102,9 @@ fn sidebar_content(current_path: &str) -> rejoice::Markup {
         (sidebar_section("Request & Response", &[
             ("/docs/request", "Request Object"),
             ("/docs/response", "Response Object"),
+            ("/docs/sessions", "Sessions"),
+            ("/docs/forms", "Forms"),
+            ("/docs/realtime", "Real-time Updates"),
         ], current_path))
 
         (sidebar_section("Features", &[
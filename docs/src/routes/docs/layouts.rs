@@ -129,6 +129,63 @@ pub async fn layout(state: AppState, req: Req, res: Res, children: Children) ->
     })
 }"#, "rust", None))
 
+        h2 { "Streaming Pages" }
+
+        p {
+            "When a page returns " code { "res.stream(...)" } " with " code { "Markup" }
+            " chunks, layouts still wrap it. Each layout is rendered first and everything before "
+            code { "(children)" } " is sent immediately, so the browser can start loading styles and scripts while the page content is still being rendered. See "
+            a href="/docs/response" { "Response" } "."
+        }
+
+        h2 { "Partial Rendering" }
+
+        p {
+            "Libraries like " a href="https://htmx.org" { "htmx" }
+            " fetch a page and swap its content into the current document, so they don't need the layouts around it. When a request carries the "
+            code { "HX-Request" } " header, the page is sent without its layouts:"
+        }
+
+        (code_block_with_filename(r#"<a hx-get="/dashboard/settings" hx-target="main">Settings</a>"#, "html", None))
+
+        p {
+            "Boosted links (" code { "HX-Boosted" } ") and history restores still get the full page. Check "
+            code { "req.is_partial()" } " to render differently for partial requests, and change the header with "
+            code { "App::partial_header" } ":"
+        }
+
+        (code_block_with_filename(r#"let app = App::new(8080, create_router()).partial_header("X-Partial");"#, "rust", None))
+
+        p { "Pages with layouts send " code { "Vary: HX-Request" } ", so caches keep the two versions apart." }
+
+        h3 { "Fragments" }
+
+        p {
+            "A route file can expose named pieces of its page as " code { "fragment_<name>" }
+            " functions. They're requested with " code { "?fragment=<name>" }
+            " on the page's URL and are never wrapped in layouts:"
+        }
+
+        (code_block_with_filename(r#"pub async fn get(req: Req, res: Res) -> Res {
+    res.html(html! {
+        h1 { "Todos" }
+        (todo_list())
+    })
+}
+
+pub async fn fragment_list(req: Req, res: Res) -> Res {
+    res.html(todo_list())
+}"#, "rust", Some("src/routes/todos.rs")))
+
+        (code_block_with_filename(r#"<div hx-get="/todos?fragment=list" hx-trigger="every 5s"></div>"#, "html", None))
+
+        p {
+            "Fragments take the same arguments as " code { "get" } ", and the file must export "
+            code { "get" } ". Unknown fragment names get a 404."
+        }
+
+        p { "Partial pages and fragments are sent as they are, without the stylesheet and scripts that full pages get." }
+
         h2 { "Authentication Pattern" }
 
         p { "Use layouts to protect groups of routes:" }
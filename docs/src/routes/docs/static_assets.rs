@@ -142,6 +142,19 @@ pub async fn get(req: Req, res: Res) -> Res {
 
         p { "You don't need to reference these manually—they're injected into all HTML responses." }
 
+        h2 { "Precompressed Files" }
+
+        p {
+            "If a static file has a " code { ".br" } " or " code { ".gz" } " sibling, such as "
+            code { "styles.css.br" } " next to " code { "styles.css" }
+            ", that copy is served to browsers that accept the encoding. " code { "rejoice build" }
+            " writes these for the JavaScript, CSS and other text assets in " code { "dist/" }
+            ". For " code { "public/" } ", add them yourself:"
+        }
+
+        (code_block_with_filename(r#"brotli -k public/data.json
+gzip -k public/data.json"#, "bash", None))
+
         h2 { "Common Static Files" }
 
         p { "Typical " code { "public/" } " contents:" }
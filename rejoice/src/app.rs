@@ -1,18 +1,65 @@
+use crate::cookies::SecretKeys;
+use crate::cors::{CorsConfig, CorsLayer};
+use crate::csrf::{CsrfConfig, CsrfLayer};
+use crate::error::ErrorPages;
+use crate::etag::ETagLayer;
+use crate::flash::FlashLayer;
+use crate::listen::{Bound, bind_tcp};
+use crate::method_override::MethodOverrideLayer;
+use crate::partial::{Partial, PartialHeader};
+use crate::proxy::TrustedProxies;
+use crate::session::{SessionConfig, SessionLayer, SessionStore};
+use crate::stream::{Injector, Streaming};
 use axum::{
-    Router,
+    Extension, Router, ServiceExt,
     body::Body,
-    http::{Request, Response, header},
+    extract::connect_info::Connected,
+    serve::{IncomingStream, Listener},
+    http::{Extensions, HeaderMap, HeaderName, Request, Response, StatusCode, Version, header},
 };
 use colored::Colorize;
+use futures::future::BoxFuture;
+use std::future::{Future, IntoFuture};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
 use std::path::Path;
+use std::sync::Arc;
 use std::task::{Context, Poll};
-use tower::{Layer, Service, ServiceBuilder};
-use tower_http::cors::{Any, CorsLayer};
+use std::time::Duration;
+use tower::{Layer, Service};
+use tower_http::compression::{CompressionLayer, DefaultPredicate, Predicate};
 use tower_http::services::ServeDir;
 
+/// Port used by `App::from_env` when `PORT` isn't set.
+const DEFAULT_PORT: u16 = 8080;
+
+/// How long shutdown waits for in-flight requests by default.
+const DEFAULT_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(30);
+
+type ShutdownHook = Box<dyn FnOnce() -> BoxFuture<'static, ()> + Send>;
+
 pub struct App {
-    port: u16,
+    addr: SocketAddr,
     router: Router<()>,
+    trusted_proxies: TrustedProxies,
+    secret_keys: Option<SecretKeys>,
+    session_store: Option<Arc<dyn SessionStore>>,
+    session_config: SessionConfig,
+    csrf: Option<CsrfConfig>,
+    cors: Option<CorsConfig>,
+    etags: bool,
+    compression: bool,
+    partial_header: PartialHeader,
+    error_pages: Option<Arc<dyn ErrorPages>>,
+    shutdown_timeout: Duration,
+    shutdown_hooks: Vec<ShutdownHook>,
+    #[cfg(unix)]
+    unix_socket: Option<std::path::PathBuf>,
+    #[cfg(unix)]
+    unix_socket_mode: Option<u32>,
+    #[cfg(feature = "tls")]
+    tls: Option<crate::tls::TlsConfig>,
+    #[cfg(feature = "tls")]
+    http_redirect: Option<u16>,
 }
 
 impl App {
@@ -20,37 +67,421 @@ impl App {
         Self::with_state(port, router, crate::NoState)
     }
 
+    /// Create an app that listens on the address given by the `HOST` and
+    /// `PORT` environment variables, defaulting to `127.0.0.1:8080`.
+    ///
+    /// Shorthand for `App::new(8080, router).bind_env()`.
+    pub fn from_env(router: Router<crate::NoState>) -> Self {
+        Self::new(DEFAULT_PORT, router).bind_env()
+    }
+
     pub fn with_state<S: Clone + Send + Sync + 'static>(
         port: u16,
         router: Router<S>,
         state: S,
     ) -> Self {
+        // Attach state to router, converting Router<S> to Router<()>
+        let router = router.with_state(state);
+
+        Self {
+            addr: SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), port),
+            router,
+            trusted_proxies: TrustedProxies::default(),
+            secret_keys: None,
+            session_store: None,
+            session_config: SessionConfig::default(),
+            csrf: None,
+            cors: None,
+            etags: false,
+            compression: true,
+            partial_header: PartialHeader::default(),
+            error_pages: None,
+            shutdown_timeout: DEFAULT_SHUTDOWN_TIMEOUT,
+            shutdown_hooks: Vec::new(),
+            #[cfg(unix)]
+            unix_socket: None,
+            #[cfg(unix)]
+            unix_socket_mode: None,
+            #[cfg(feature = "tls")]
+            tls: None,
+            #[cfg(feature = "tls")]
+            http_redirect: None,
+        }
+    }
+
+    /// Listen on this IP address instead of `127.0.0.1`.
+    ///
+    /// Use `0.0.0.0` (or `::` for IPv6 too) to accept connections from other
+    /// machines, e.g. inside a Docker container. Square brackets around IPv6
+    /// addresses are optional.
+    ///
+    /// # Panics
+    /// Panics if `host` is not an IP address.
+    pub fn host(mut self, host: &str) -> Self {
+        let ip = parse_host(host)
+            .unwrap_or_else(|| panic!("{:?} is not a valid IP address", host));
+        self.addr.set_ip(ip);
+        self
+    }
+
+    /// Listen on this port.
+    pub fn port(mut self, port: u16) -> Self {
+        self.addr.set_port(port);
+        self
+    }
+
+    /// Listen on this address, replacing the host and port.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let app = App::new(8080, create_router()).bind("[::]:3000".parse().unwrap());
+    /// ```
+    pub fn bind(mut self, addr: SocketAddr) -> Self {
+        self.addr = addr;
+        self
+    }
+
+    /// Listen on a Unix domain socket at `path` instead of TCP.
+    ///
+    /// Useful behind a reverse proxy on the same host. A socket file left
+    /// over from a previous run is replaced, and the file is removed on
+    /// shutdown. Add `"unix"` to `App::trusted_proxies` to honor the proxy's
+    /// forwarding headers.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let app = App::new(8080, create_router())
+    ///     .unix_socket("/run/my-app/app.sock")
+    ///     .unix_socket_mode(0o660)
+    ///     .trusted_proxies(["unix"]);
+    /// ```
+    #[cfg(unix)]
+    pub fn unix_socket(mut self, path: impl Into<std::path::PathBuf>) -> Self {
+        self.unix_socket = Some(path.into());
+        self
+    }
+
+    /// Set the permissions of the Unix socket file, e.g. `0o660` to let a
+    /// proxy in the same group connect. Defaults to what the umask allows.
+    #[cfg(unix)]
+    pub fn unix_socket_mode(mut self, mode: u32) -> Self {
+        self.unix_socket_mode = Some(mode);
+        self
+    }
+
+    /// Take the host and port from the `HOST` and `PORT` environment
+    /// variables, where set.
+    ///
+    /// # Example
+    /// ```ignore
+    /// // HOST=0.0.0.0 PORT=3000 ./my-app
+    /// let app = App::with_state(8080, create_router(), state).bind_env();
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if `HOST` is not an IP address or `PORT` is not a port number.
+    pub fn bind_env(mut self) -> Self {
+        if let Ok(host) = std::env::var("HOST") {
+            let ip = parse_host(&host)
+                .unwrap_or_else(|| panic!("HOST must be an IP address, got {:?}", host));
+            self.addr.set_ip(ip);
+        }
+        if let Ok(port) = std::env::var("PORT") {
+            let port = port
+                .trim()
+                .parse()
+                .unwrap_or_else(|_| panic!("PORT must be a port number, got {:?}", port));
+            self.addr.set_port(port);
+        }
+        self
+    }
+
+    /// Trust forwarding headers from these proxies.
+    ///
+    /// Accepts IP addresses and CIDR ranges, plus `"unix"` for peers on a Unix
+    /// socket. When a request arrives from a trusted proxy, `Req::client_ip`
+    /// and `Req::is_secure` are resolved from `Forwarded`, `X-Forwarded-For`
+    /// and `X-Forwarded-Proto`.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let app = App::new(8080, create_router())
+    ///     .trusted_proxies(["127.0.0.1", "::1", "10.0.0.0/8"]);
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if an entry is not a valid IP address, CIDR range or `"unix"`.
+    pub fn trusted_proxies<I, S>(mut self, proxies: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.trusted_proxies = TrustedProxies::parse(proxies).unwrap_or_else(|e| panic!("{}", e));
+        self
+    }
+
+    /// Set the secret key used for signed and private cookies, which also
+    /// signs flash messages.
+    ///
+    /// Load it from the environment rather than hardcoding it.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let app = App::new(8080, create_router())
+    ///     .secret_key(std::env::var("SECRET_KEY").unwrap());
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if the key is shorter than 32 bytes.
+    pub fn secret_key(mut self, secret: impl AsRef<[u8]>) -> Self {
+        self.secret_keys = Some(SecretKeys::new(secret.as_ref()));
+        self
+    }
+
+    /// Accept cookies signed or encrypted with an older secret key.
+    ///
+    /// Use this to rotate keys: set the new key with `secret_key` and keep the
+    /// old one here until existing cookies have expired. Old keys are only used
+    /// for reading.
+    ///
+    /// # Panics
+    /// Panics if called before `secret_key`, or if the key is shorter than
+    /// 32 bytes.
+    pub fn previous_secret_key(mut self, secret: impl AsRef<[u8]>) -> Self {
+        self.secret_keys
+            .as_mut()
+            .expect("App::previous_secret_key must be called after App::secret_key")
+            .add_previous(secret.as_ref());
+        self
+    }
+
+    /// Enable server-side sessions, available through `req.session()`.
+    ///
+    /// # Example
+    /// ```ignore
+    /// use rejoice::session::MemoryStore;
+    ///
+    /// let app = App::new(8080, create_router()).sessions(MemoryStore::new());
+    /// ```
+    pub fn sessions(mut self, store: impl SessionStore) -> Self {
+        self.session_store = Some(Arc::new(store));
+        self
+    }
+
+    /// Configure the session cookie and expiry.
+    pub fn session_config(mut self, config: SessionConfig) -> Self {
+        self.session_config = config;
+        self
+    }
+
+    /// Enable CSRF protection for POST, PUT, PATCH and DELETE requests.
+    ///
+    /// Forms must include `csrf_field()`, and other clients must send the
+    /// token in an `X-CSRF-Token` header. Requires sessions.
+    ///
+    /// # Example
+    /// ```ignore
+    /// use rejoice::csrf::CsrfConfig;
+    ///
+    /// let app = App::new(8080, create_router())
+    ///     .sessions(MemoryStore::new())
+    ///     .csrf(CsrfConfig::default().exempt("/api"));
+    /// ```
+    pub fn csrf(mut self, config: CsrfConfig) -> Self {
+        self.csrf = Some(config);
+        self
+    }
+
+    /// Set which other origins may call the app from the browser.
+    ///
+    /// Without this, any origin is allowed in development and none in
+    /// production.
+    ///
+    /// # Example
+    /// ```ignore
+    /// use rejoice::cors::CorsConfig;
+    ///
+    /// let app = App::new(8080, create_router()).cors(
+    ///     CorsConfig::default()
+    ///         .allow_origin("https://app.example.com")
+    ///         .allow_credentials(true)
+    ///         .route("/api/public", CorsConfig::permissive()),
+    /// );
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if credentials are allowed together with any origin.
+    pub fn cors(mut self, config: CorsConfig) -> Self {
+        config.validate();
+        self.cors = Some(config);
+        self
+    }
+
+    /// Answer repeat requests for unchanged pages with 304 Not Modified.
+    ///
+    /// HTML and JSON responses get a weak `ETag` computed from the final
+    /// body, and responses with an explicit `ETag` or `Last-Modified` (see
+    /// `Res::set_etag`) are checked against the request's cache headers.
+    pub fn etags(mut self) -> Self {
+        self.etags = true;
+        self
+    }
+
+    /// Turn gzip and brotli compression of responses on or off.
+    ///
+    /// On by default. Turn it off when a reverse proxy already compresses
+    /// responses.
+    pub fn compression(mut self, enabled: bool) -> Self {
+        self.compression = enabled;
+        self
+    }
+
+    /// Set the request header that asks for a page without its layouts
+    /// (default `HX-Request`, sent by htmx).
+    ///
+    /// # Panics
+    /// Panics if `name` is not a valid header name.
+    pub fn partial_header(mut self, name: &str) -> Self {
+        let name = HeaderName::try_from(name)
+            .unwrap_or_else(|_| panic!("{:?} is not a valid header name", name));
+        self.partial_header = PartialHeader(name);
+        self
+    }
+
+    /// Render error responses with `pages` instead of the built-in ones.
+    ///
+    /// Used by `Res::bad_request` and the other error helpers, `Error`
+    /// results and CSRF rejections. See `ErrorPages`.
+    pub fn error_pages(mut self, pages: impl ErrorPages) -> Self {
+        self.error_pages = Some(Arc::new(pages));
+        self
+    }
+
+    /// Serve HTTPS with a PEM certificate chain and private key.
+    ///
+    /// The files are reloaded when they change, so renewed certificates take
+    /// effect without a restart. Requires the `tls` feature.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let app = App::new(443, create_router())
+    ///     .host("0.0.0.0")
+    ///     .tls("/etc/letsencrypt/live/example.com/fullchain.pem", "/etc/letsencrypt/live/example.com/privkey.pem")
+    ///     .redirect_http(80);
+    /// ```
+    #[cfg(feature = "tls")]
+    pub fn tls(
+        mut self,
+        cert: impl Into<std::path::PathBuf>,
+        key: impl Into<std::path::PathBuf>,
+    ) -> Self {
+        self.tls = Some(crate::tls::TlsConfig {
+            cert: cert.into(),
+            key: key.into(),
+        });
+        self
+    }
+
+    /// Also listen for plain HTTP on `port`, on the same host, and redirect
+    /// every request to the HTTPS URL.
+    ///
+    /// # Panics
+    /// `run` panics if TLS isn't enabled with `App::tls`.
+    #[cfg(feature = "tls")]
+    pub fn redirect_http(mut self, port: u16) -> Self {
+        self.http_redirect = Some(port);
+        self
+    }
+
+    /// Set how long shutdown waits for in-flight requests to finish
+    /// (default 30 seconds).
+    ///
+    /// On SIGTERM or Ctrl+C the server stops accepting connections and lets
+    /// open requests complete. Connections still open after `timeout`, such as
+    /// long-lived event streams, are closed.
+    pub fn shutdown_timeout(mut self, timeout: Duration) -> Self {
+        self.shutdown_timeout = timeout;
+        self
+    }
+
+    /// Run `hook` after the server has shut down, e.g. to close a database
+    /// pool or flush queued jobs.
+    ///
+    /// Hooks run in the order they were added, after in-flight requests have
+    /// finished or the shutdown timeout has passed.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let db = state.db.clone();
+    /// let app = App::with_state(8080, create_router(), state)
+    ///     .on_shutdown(move || async move { db.close().await });
+    /// ```
+    pub fn on_shutdown<F, Fut>(mut self, hook: F) -> Self
+    where
+        F: FnOnce() -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        self.shutdown_hooks.push(Box::new(move || Box::pin(hook())));
+        self
+    }
+
+    /// Assemble the final router with static files and middleware.
+    fn into_router(self) -> Router {
         let dev_mode = std::env::var("REJOICE_DEV").is_ok();
         let has_islands = Path::new("dist/islands.js").exists();
         let has_styles = Path::new("dist/styles.css").exists();
 
+        let mut router = self.router;
+
+        if let Some(config) = self.csrf {
+            assert!(
+                self.session_store.is_some(),
+                "CSRF protection requires sessions; call App::sessions"
+            );
+            router = router.layer(CsrfLayer {
+                config: Arc::new(config),
+            });
+        }
+
+        // Sessions only apply to route handlers, not static files
+        if let Some(store) = self.session_store {
+            router = router.layer(SessionLayer {
+                store,
+                config: Arc::new(self.session_config),
+            });
+        }
+
+        // Flash messages only apply to route handlers, not static files
+        router = router.layer(FlashLayer);
+
         // Serve static files from dist/ directory (built JS/CSS)
         let static_dir = Path::new("dist");
-        let mut router = if static_dir.exists() {
-            router.nest_service("/static", ServeDir::new(static_dir))
-        } else {
-            router
-        };
+        if static_dir.exists() {
+            // `rejoice build` writes .br and .gz copies of each asset
+            router = router.nest_service(
+                "/static",
+                ServeDir::new(static_dir)
+                    .precompressed_br()
+                    .precompressed_gzip(),
+            );
+        }
 
         // Serve public/ directory at root (for images, fonts, etc.)
         let public_dir = Path::new("public");
         if public_dir.exists() {
-            router = router.fallback_service(ServeDir::new(public_dir));
+            router = router.fallback_service(
+                ServeDir::new(public_dir)
+                    .precompressed_br()
+                    .precompressed_gzip(),
+            );
         }
 
-        router = router.layer(
-            ServiceBuilder::new().layer(
-                CorsLayer::new()
-                    .allow_headers(Any)
-                    .allow_methods(Any)
-                    .allow_origin(Any),
-            ),
-        );
+        // Without a policy, allow any origin in development only
+        if let Some(config) = self.cors.or_else(|| dev_mode.then(CorsConfig::permissive)) {
+            router = router.layer(CorsLayer {
+                config: Arc::new(config),
+            });
+        }
 
         // Add script/style injection middleware
         router = router.layer(ScriptInjectionLayer {
@@ -59,30 +490,265 @@ impl App {
             has_styles,
         });
 
-        // Attach state to router, converting Router<S> to Router<()>
-        let router = router.with_state(state);
+        // Runs after script injection so the injected markup is hashed too
+        if self.etags {
+            router = router.layer(ETagLayer);
+        }
 
-        Self { port, router }
+        // Outermost, so injected markup is compressed and the ETag describes
+        // the uncompressed body
+        if self.compression {
+            router = router.layer(CompressionLayer::new().compress_when(compress_predicate()));
+        }
+
+        router = router.layer(Extension(Arc::new(self.trusted_proxies)));
+        router = router.layer(Extension(Arc::new(self.partial_header)));
+        if let Some(keys) = self.secret_keys {
+            router = router.layer(Extension(Arc::new(keys)));
+        }
+        if let Some(pages) = self.error_pages {
+            router = router.layer(Extension(pages));
+        }
+
+        router
     }
 
+    /// Start the server.
+    ///
+    /// Exits the process with an error message if the address can't be
+    /// bound, e.g. because another process is using the port. Use
+    /// `try_run` to handle that yourself.
     pub async fn run(self) {
-        let listener = tokio::net::TcpListener::bind(&format!("127.0.0.1:{}", self.port))
-            .await
-            .unwrap();
+        if let Err(error) = self.try_run().await {
+            eprintln!("{} {}", "error:".red().bold(), error);
+            if error.kind() == std::io::ErrorKind::AddrInUse {
+                eprintln!("Another process is listening there; stop it or choose another address");
+            }
+            std::process::exit(1);
+        }
+    }
 
+    /// Start the server, returning an error if the address can't be bound or
+    /// the server fails.
+    ///
+    /// Returns once the server has shut down gracefully after SIGTERM or
+    /// Ctrl+C and the `on_shutdown` hooks have run.
+    pub async fn try_run(mut self) -> std::io::Result<()> {
+        let bound = self.listen().await?;
         let dev_mode = std::env::var("REJOICE_DEV").is_ok();
-        if dev_mode {
-            println!(
-                "{} {} {}",
-                "✓".green().bold(),
-                "Server running at".white(),
-                format!("http://localhost:{}", self.port).cyan().underline()
+        let shutdown_timeout = self.shutdown_timeout;
+        let hooks = std::mem::take(&mut self.shutdown_hooks);
+
+        let result = match bound {
+            Bound::Tcp(listener) => {
+                let addr = listener.local_addr()?;
+
+                #[cfg(feature = "tls")]
+                if let Some(config) = self.tls.take() {
+                    use axum::serve::ListenerExt;
+
+                    let listener = crate::tls::TlsListener::new(listener, &config)?;
+                    let redirect = match self.http_redirect {
+                        Some(port) => {
+                            let http = bind_tcp(SocketAddr::new(addr.ip(), port)).await?;
+                            let router = crate::tls::redirect_router(addr.port());
+                            Some(tokio::spawn(async move { axum::serve(http, router).await }))
+                        }
+                        None => None,
+                    };
+
+                    announce(&display_url("https", addr), dev_mode);
+                    let router = self
+                        .into_router()
+                        .layer(Extension(crate::request::SecureConnection));
+                    // `TapIo` provides the peer address as `ConnectInfo` for any listener
+                    let listener = listener.tap_io(|_| {});
+                    let result =
+                        serve::<_, SocketAddr>(listener, router, shutdown_timeout, dev_mode).await;
+
+                    if let Some(redirect) = redirect {
+                        redirect.abort();
+                    }
+                    run_hooks(hooks).await;
+                    return result;
+                }
+
+                #[cfg(feature = "tls")]
+                assert!(
+                    self.http_redirect.is_none(),
+                    "Redirecting HTTP to HTTPS requires TLS; call App::tls"
+                );
+
+                announce(&display_url("http", addr), dev_mode);
+                serve::<_, SocketAddr>(listener, self.into_router(), shutdown_timeout, dev_mode)
+                    .await
+            }
+            #[cfg(unix)]
+            Bound::Unix(listener, created) => {
+                use axum::serve::ListenerExt;
+
+                #[cfg(feature = "tls")]
+                assert!(
+                    self.tls.is_none(),
+                    "TLS isn't supported on Unix sockets; terminate TLS in the reverse proxy"
+                );
+
+                let path = listener.local_addr()?;
+                let path = path.as_pathname().unwrap_or(std::path::Path::new("?"));
+                announce(&format!("unix:{}", path.display()), dev_mode);
+
+                let listener = listener.tap_io(|_| {});
+                let result = serve::<_, tokio::net::unix::SocketAddr>(
+                    listener,
+                    self.into_router(),
+                    shutdown_timeout,
+                    dev_mode,
+                )
+                .await;
+
+                if let Some(path) = created {
+                    let _ = std::fs::remove_file(path);
+                }
+                result
+            }
+        };
+
+        run_hooks(hooks).await;
+        result
+    }
+
+    /// Take the socket passed by systemd socket activation, or bind the
+    /// configured Unix socket or address.
+    async fn listen(&self) -> std::io::Result<Bound> {
+        #[cfg(unix)]
+        if let Some(bound) = crate::listen::inherited()? {
+            return Ok(bound);
+        }
+
+        #[cfg(unix)]
+        if let Some(path) = &self.unix_socket {
+            let listener = crate::listen::bind_unix(path, self.unix_socket_mode)?;
+            return Ok(Bound::Unix(listener, Some(path.clone())));
+        }
+
+        bind_tcp(self.addr).await.map(Bound::Tcp)
+    }
+}
+
+fn announce(url: &str, dev_mode: bool) {
+    if dev_mode {
+        println!(
+            "{} {} {}",
+            "✓".green().bold(),
+            "Server running at".white(),
+            url.cyan().underline()
+        );
+    } else {
+        println!("Listening on {}", url);
+    }
+}
+
+/// Serve `router` until a shutdown signal, then wait up to
+/// `shutdown_timeout` for open connections.
+///
+/// Requests get the peer address as `ConnectInfo<C>`.
+async fn serve<L, C>(
+    listener: L,
+    router: Router,
+    shutdown_timeout: Duration,
+    dev_mode: bool,
+) -> std::io::Result<()>
+where
+    L: Listener,
+    L::Addr: std::fmt::Debug,
+    C: for<'a> Connected<IncomingStream<'a, L>> + Clone + Send + Sync + 'static,
+{
+    // Method override has to wrap the router so it runs before routing
+    let service = MethodOverrideLayer.layer(router);
+
+    let (draining_tx, draining_rx) = tokio::sync::oneshot::channel();
+    let server = axum::serve(
+        listener,
+        ServiceExt::<Request<Body>>::into_make_service_with_connect_info::<C>(service),
+    )
+    .with_graceful_shutdown(async move {
+        shutdown_signal().await;
+        if !dev_mode {
+            println!("Shutting down, waiting for in-flight requests...");
+        }
+        let _ = draining_tx.send(());
+    });
+
+    // Serving only ends on its own once every connection has closed, so
+    // stop waiting when the timeout runs out after the signal
+    let deadline = async {
+        match draining_rx.await {
+            Ok(()) => tokio::time::sleep(shutdown_timeout).await,
+            Err(_) => std::future::pending().await,
+        }
+    };
+    tokio::select! {
+        result = server.into_future() => result,
+        () = deadline => {
+            eprintln!(
+                "{} closing connections still open after {:?}",
+                "warning:".yellow().bold(),
+                shutdown_timeout
             );
-        } else {
-            println!("Listening on http://localhost:{}", self.port);
+            Ok(())
         }
+    }
+}
 
-        axum::serve(listener, self.router).await.unwrap();
+async fn run_hooks(hooks: Vec<ShutdownHook>) {
+    for hook in hooks {
+        hook().await;
+    }
+}
+
+/// Resolve on Ctrl+C, or SIGTERM on Unix.
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        if tokio::signal::ctrl_c().await.is_err() {
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(_) => std::future::pending().await,
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        () = ctrl_c => {}
+        () = terminate => {}
+    }
+}
+
+/// Parse an IP address, with or without the brackets used for IPv6 in URLs.
+fn parse_host(host: &str) -> Option<IpAddr> {
+    let host = host.trim();
+    let host = host
+        .strip_prefix('[')
+        .and_then(|h| h.strip_suffix(']'))
+        .unwrap_or(host);
+    host.parse().ok()
+}
+
+/// The URL printed on startup. Loopback and wildcard addresses are shown as
+/// `localhost`, since that reaches the server from the same machine.
+fn display_url(scheme: &str, addr: SocketAddr) -> String {
+    if addr.ip().is_loopback() || addr.ip().is_unspecified() {
+        format!("{}://localhost:{}", scheme, addr.port())
+    } else {
+        format!("{}://{}", scheme, addr)
     }
 }
 
@@ -95,6 +761,21 @@ const LIVE_RELOAD_SCRIPT: &str = concat!(
 const ISLAND_SCRIPT: &str = r#"<script type="module" src="/static/islands.js"></script>"#;
 const STYLES_LINK: &str = r#"<link rel="stylesheet" href="/static/styles.css">"#;
 
+/// Which responses to compress on the fly.
+///
+/// Byte-range responses are left alone, since ranges must refer to the
+/// uncompressed file. So are streamed pages: the encoder buffers output, which
+/// would hold back the early flushes streaming is for.
+fn compress_predicate() -> impl Predicate {
+    DefaultPredicate::new().and(
+        |_: StatusCode, _: Version, headers: &HeaderMap, extensions: &Extensions| {
+            !headers.contains_key(header::ACCEPT_RANGES)
+                && !headers.contains_key(header::CONTENT_RANGE)
+                && extensions.get::<Streaming>().is_none()
+        },
+    )
+}
+
 #[derive(Clone)]
 pub struct ScriptInjectionLayer {
     dev_mode: bool,
@@ -145,7 +826,7 @@ where
         let has_styles = self.has_styles;
 
         Box::pin(async move {
-            let response = inner.call(req).await?;
+            let mut response = inner.call(req).await?;
 
             // Check if this is an HTML response
             let is_html = response
@@ -155,7 +836,20 @@ where
                 .map(|v| v.contains("text/html"))
                 .unwrap_or(false);
 
-            if !is_html {
+            // Partial and empty responses must be passed through untouched
+            let has_full_body = !matches!(
+                response.status(),
+                StatusCode::NO_CONTENT
+                    | StatusCode::PARTIAL_CONTENT
+                    | StatusCode::NOT_MODIFIED
+                    | StatusCode::RANGE_NOT_SATISFIABLE
+            );
+
+            // Partial pages and fragments are swapped into a document that
+            // already has the scripts and styles
+            let is_partial = response.extensions().get::<Partial>().is_some();
+
+            if !is_html || !has_full_body || is_partial {
                 return Ok(response);
             }
 
@@ -178,6 +872,16 @@ where
                 return Ok(response);
             }
 
+            // Injecting changes the length
+            response.headers_mut().remove(header::CONTENT_LENGTH);
+
+            // Streamed bodies are injected as they flow, so they still flush early
+            if response.extensions().get::<Streaming>().is_some() {
+                let (parts, body) = response.into_parts();
+                let body = Injector::new(head_inject, scripts).wrap(body);
+                return Ok(Response::from_parts(parts, body));
+            }
+
             // Read the body and inject
             let (parts, body) = response.into_parts();
             let bytes = axum::body::to_bytes(body, usize::MAX)
@@ -1,9 +1,16 @@
 use super::islands::{generate_islands_registry, generate_vite_config, has_island_components};
 use super::style;
 use colored::Colorize;
+use std::io::Write;
 use std::path::Path;
 use std::process::{Command, Stdio};
 
+/// Extensions of assets worth precompressing. Images and fonts are already
+/// compressed.
+const COMPRESSIBLE_EXTENSIONS: &[&str] = &[
+    "js", "mjs", "css", "html", "svg", "json", "map", "txt", "xml", "wasm",
+];
+
 pub fn build_command(release: bool) {
     style::print_banner();
 
@@ -19,14 +26,14 @@ pub fn build_command(release: bool) {
 
     // Determine total steps:
     // - No client dir: just cargo build (1 step)
-    // - Client dir with islands: npm install, generate islands, build assets, cargo build (4 steps)
-    // - Client dir without islands: npm install, build assets, cargo build (3 steps)
+    // - Client dir with islands: npm install, generate islands, build assets, compress assets, cargo build (5 steps)
+    // - Client dir without islands: npm install, build assets, compress assets, cargo build (4 steps)
     let total_steps = if !has_client {
         1
     } else if has_islands {
-        4
+        5
     } else {
-        3
+        4
     };
     let mut step = 1;
 
@@ -72,9 +79,17 @@ pub fn build_command(release: bool) {
             std::process::exit(1);
         }
         step += 1;
+
+        // Step 4 (or 3): Write .br and .gz copies for the static file server
+        style::print_step(step, total_steps, "Compressing client assets...");
+        if let Err(e) = compress_assets(Path::new("dist")) {
+            style::print_error(&format!("Failed to compress client assets: {}", e));
+            std::process::exit(1);
+        }
+        step += 1;
     }
 
-    // Step 4 (or 1): Build Rust binary
+    // Step 5 (or 1): Build Rust binary
     let build_msg = if release {
         "Building Rust binary (release)..."
     } else {
@@ -108,6 +123,41 @@ pub fn build_command(release: bool) {
     }
 }
 
+/// Write brotli and gzip copies of every compressible file under `dir`, so
+/// they can be served without compressing on each request.
+fn compress_assets(dir: &Path) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            compress_assets(&path)?;
+            continue;
+        }
+
+        let compressible = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| COMPRESSIBLE_EXTENSIONS.contains(&ext));
+        if !compressible {
+            continue;
+        }
+
+        let contents = std::fs::read(&path)?;
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+
+        let mut gzip = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::best());
+        gzip.write_all(&contents)?;
+        std::fs::write(path.with_file_name(format!("{}.gz", file_name)), gzip.finish()?)?;
+
+        let mut brotli = Vec::new();
+        {
+            let mut writer = brotli::CompressorWriter::new(&mut brotli, 4096, 11, 22);
+            writer.write_all(&contents)?;
+        }
+        std::fs::write(path.with_file_name(format!("{}.br", file_name)), brotli)?;
+    }
+    Ok(())
+}
+
 fn print_deployment_info(_has_client: bool) {
     let binary_name = get_project_name().unwrap_or_else(|| "your-app".to_string());
 
@@ -14,6 +14,9 @@ use std::sync::Arc;
 use std::time::{Duration, Instant};
 use tokio::sync::broadcast;
 
+/// How long a restart waits for the old app to shut down before killing it.
+const STOP_TIMEOUT: Duration = Duration::from_secs(5);
+
 pub fn dev_command() {
     let rt = tokio::runtime::Runtime::new().unwrap();
     rt.block_on(run_dev_server());
@@ -152,16 +155,16 @@ fn run_watch_loop(
             Ok(Ok(event)) => {
                 use notify::EventKind::*;
                 match event.kind {
-                    Create(_) | Modify(_) | Remove(_) => {
-                        if last_restart.elapsed() > debounce_duration {
-                            handle_file_change(
-                                &event,
-                                child,
-                                has_islands,
-                                &reload_tx,
-                                &mut last_restart,
-                            );
-                        }
+                    Create(_) | Modify(_) | Remove(_)
+                        if last_restart.elapsed() > debounce_duration =>
+                    {
+                        handle_file_change(
+                            &event,
+                            child,
+                            has_islands,
+                            &reload_tx,
+                            &mut last_restart,
+                        );
                     }
                     _ => {}
                 }
@@ -245,8 +248,7 @@ fn handle_rust_change(
 
     style::print_compiling();
 
-    let _ = child.kill();
-    let _ = child.wait();
+    stop_app(child);
 
     *child = start_app();
     *last_restart = Instant::now();
@@ -266,6 +268,30 @@ fn run_vite_build() {
         .status();
 }
 
+/// Stop the app gracefully so in-flight requests finish and `on_shutdown`
+/// hooks run, killing it if it takes longer than `STOP_TIMEOUT`.
+fn stop_app(child: &mut Child) {
+    #[cfg(unix)]
+    {
+        use nix::sys::signal::{Signal, kill};
+        use nix::unistd::Pid;
+
+        if kill(Pid::from_raw(child.id() as i32), Signal::SIGTERM).is_ok() {
+            let deadline = Instant::now() + STOP_TIMEOUT;
+            while Instant::now() < deadline {
+                match child.try_wait() {
+                    Ok(Some(_)) => return,
+                    Ok(None) => std::thread::sleep(Duration::from_millis(20)),
+                    Err(_) => break,
+                }
+            }
+        }
+    }
+
+    let _ = child.kill();
+    let _ = child.wait();
+}
+
 fn start_app() -> Child {
     Command::new("cargo")
         .args(["run", "--quiet"])
@@ -168,9 +168,11 @@ async fn main() {
     })
     .await;
 
-    let state = AppState { db: pool };
+    let state = AppState { db: pool.clone() };
 
-    let app = App::with_state(8080, create_router(), state);
+    let app = App::with_state(8080, create_router(), state)
+        .bind_env()
+        .on_shutdown(move || async move { pool.close().await });
     app.run().await;
 }
 "#
@@ -181,7 +183,7 @@ rejoice::routes!();
 
 #[tokio::main]
 async fn main() {
-    let app = App::new(8080, create_router());
+    let app = App::from_env(create_router());
     app.run().await;
 }
 "#
@@ -50,13 +50,16 @@ fn generate_routes_file(
     output.push_str(&format!("#[path = {:?}]\n", routes_rs_path.display()));
     output.push_str("mod routes;\n\n");
 
-    // Generate wrapper handlers for routes with layouts
+    // Generate wrapper handlers. Every route gets one, since axum can't route
+    // handlers that take Req before Res and handlers may return Result<Res, E>.
     for route in routes {
         for method in &route.methods {
-            if let Some(wrapper) = generate_wrapper_handler(route, method, layouts, stateless) {
-                output.push_str(&wrapper);
-                output.push_str("\n\n");
-            }
+            output.push_str(&generate_wrapper_handler(route, method, layouts, stateless));
+            output.push_str("\n\n");
+        }
+        if route.has_ws {
+            output.push_str(&generate_ws_handler(route, stateless));
+            output.push_str("\n\n");
         }
     }
 
@@ -68,13 +71,16 @@ fn generate_routes_file(
         let mut method_handlers = Vec::new();
 
         for method in &route.methods {
-            let handler = if has_layouts(route, layouts) {
-                format!("wrapper_{}_{}", route.mod_name, method)
-            } else {
-                format!("routes::{}::{}", route.mod_name, method)
-            };
+            // The WebSocket handler answers GET and hands plain requests to `get`
+            if route.has_ws && method == "get" {
+                continue;
+            }
+            let handler = format!("wrapper_{}_{}", route.mod_name, method);
             method_handlers.push((method.as_str(), handler));
         }
+        if route.has_ws {
+            method_handlers.insert(0, ("get", format!("wrapper_{}_ws", route.mod_name)));
+        }
 
         if method_handlers.is_empty() {
             continue;
@@ -108,6 +114,76 @@ struct RouteInfo {
     dir_path: String,
     param: Option<String>,
     methods: Vec<String>,
+    has_ws: bool,
+    /// Names of `fragment_<name>` handlers, served with `?fragment=<name>`
+    fragments: Vec<String>,
+}
+
+/// Read a route file with string literals and comments blanked out, so
+/// example code in doc strings isn't mistaken for WebSocket or fragment
+/// handlers.
+fn read_code(file_path: &Path) -> Option<String> {
+    let content = fs::read_to_string(file_path).ok()?;
+    let chars: Vec<char> = content.chars().collect();
+    let mut code = String::with_capacity(content.len());
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+
+        if c == '/' && next == Some('/') {
+            while i < chars.len() && chars[i] != '\n' {
+                i += 1;
+            }
+        } else if c == '/' && next == Some('*') {
+            i += 2;
+            while i < chars.len() && !(chars[i] == '*' && chars.get(i + 1) == Some(&'/')) {
+                i += 1;
+            }
+            i += 2;
+        } else if c == 'r' && matches!(next, Some('"') | Some('#')) {
+            // Raw string: r"..." or r#"..."#
+            let hashes = chars[i + 1..].iter().take_while(|c| **c == '#').count();
+            if chars.get(i + 1 + hashes) != Some(&'"') {
+                code.push(c);
+                i += 1;
+                continue;
+            }
+            let closes = |i: usize| {
+                chars[i] == '"' && chars[i + 1..].iter().take_while(|c| **c == '#').count() >= hashes
+            };
+            i += hashes + 2;
+            while i < chars.len() && !closes(i) {
+                i += 1;
+            }
+            i += hashes + 1;
+            code.push_str("\"\"");
+        } else if c == '"' {
+            i += 1;
+            while i < chars.len() && chars[i] != '"' {
+                if chars[i] == '\\' {
+                    i += 1;
+                }
+                i += 1;
+            }
+            i += 1;
+            code.push_str("\"\"");
+        } else if c == '\'' && (next == Some('\\') || chars.get(i + 2) == Some(&'\'')) {
+            // Char literal such as '"' or '\''; lifetimes are left alone
+            i += if next == Some('\\') { 2 } else { 1 };
+            while i < chars.len() && chars[i] != '\'' {
+                i += 1;
+            }
+            i += 1;
+            code.push_str("' '");
+        } else {
+            code.push(c);
+            i += 1;
+        }
+    }
+
+    Some(code)
 }
 
 fn detect_methods(file_path: &Path) -> Vec<String> {
@@ -126,6 +202,22 @@ fn detect_methods(file_path: &Path) -> Vec<String> {
     methods
 }
 
+fn detect_fragments(file_path: &Path) -> Vec<String> {
+    let content = read_code(file_path).unwrap_or_default();
+
+    content
+        .split("pub async fn fragment_")
+        .skip(1)
+        .filter_map(|rest| {
+            let name: String = rest
+                .chars()
+                .take_while(|c| c.is_ascii_alphanumeric() || *c == '_')
+                .collect();
+            (!name.is_empty() && rest[name.len()..].starts_with('(')).then_some(name)
+        })
+        .collect()
+}
+
 fn collect_layouts_and_routes(
     dir: &Path,
     url_prefix: &str,
@@ -198,6 +290,8 @@ fn collect_layouts_and_routes(
             };
 
             let methods = detect_methods(&path);
+            let has_ws = read_code(&path).is_some_and(|code| code.contains("pub async fn ws("));
+            let fragments = detect_fragments(&path);
 
             routes.push(RouteInfo {
                 url_path,
@@ -205,16 +299,14 @@ fn collect_layouts_and_routes(
                 dir_path: dir_path.clone(),
                 param,
                 methods,
+                has_ws,
+                fragments,
             });
         }
     }
 }
 
-fn has_layouts(route: &RouteInfo, layouts: &HashMap<String, String>) -> bool {
-    get_layout_chain(route, layouts).is_some()
-}
-
-fn get_layout_chain(route: &RouteInfo, layouts: &HashMap<String, String>) -> Option<Vec<String>> {
+fn get_layout_chain(route: &RouteInfo, layouts: &HashMap<String, String>) -> Vec<String> {
     let mut chain = Vec::new();
 
     if let Some(layout_mod) = layouts.get("") {
@@ -237,11 +329,7 @@ fn get_layout_chain(route: &RouteInfo, layouts: &HashMap<String, String>) -> Opt
         }
     }
 
-    if chain.is_empty() {
-        None
-    } else {
-        Some(chain)
-    }
+    chain
 }
 
 fn generate_wrapper_handler(
@@ -249,54 +337,63 @@ fn generate_wrapper_handler(
     method: &str,
     layouts: &HashMap<String, String>,
     stateless: bool,
-) -> Option<String> {
-    let chain = get_layout_chain(route, layouts)?;
+) -> String {
+    let chain = get_layout_chain(route, layouts);
 
     let mut output = String::new();
 
-    // Function signature - Req must be last since it implements FromRequest (consumes body)
-    if let Some(param) = &route.param {
-        output.push_str(&format!(
-            "async fn wrapper_{}_{}(\n    rejoice::State(state): rejoice::State<__RejoiceState>,\n    rejoice::Path({param}): rejoice::Path<String>,\n    req: rejoice::Req,\n) -> rejoice::Res {{\n",
-            route.mod_name, method
-        ));
-
-        output.push_str("    let res = rejoice::Res::new();\n");
-        if stateless {
-            output.push_str(&format!(
-                "    let _ = state;\n    let res = routes::{}::{}(req.clone(), res, {param}).await;\n",
-                route.mod_name, method
-            ));
-        } else {
-            output.push_str(&format!(
-                "    let res = routes::{}::{}(state.clone(), req.clone(), res, {param}).await;\n",
-                route.mod_name, method
-            ));
-        }
+    // Function signature - Req must be last since it implements FromRequest (consumes body).
+    // Res is extracted so it carries the request context; layouts get fresh copies of it.
+    let (param_extractor, param_arg) = match &route.param {
+        Some(param) => (
+            format!("    rejoice::Path({param}): rejoice::Path<String>,\n"),
+            format!(", {param}"),
+        ),
+        None => (String::new(), String::new()),
+    };
+    output.push_str(&format!(
+        "async fn wrapper_{}_{}(\n    rejoice::State(state): rejoice::State<__RejoiceState>,\n{param_extractor}    res: rejoice::Res,\n    req: rejoice::Req,\n) -> rejoice::Res {{\n",
+        route.mod_name, method
+    ));
+
+    // Handlers may return Res or Result<Res, E>; errors are rendered into a
+    // fresh Res so they go through the layouts like any other page
+    output.push_str("    let base = res.fresh();\n");
+    let state_arg = if stateless {
+        output.push_str("    let _ = state;\n");
+        ""
     } else {
-        output.push_str(&format!(
-            "async fn wrapper_{}_{}(\n    rejoice::State(state): rejoice::State<__RejoiceState>,\n    req: rejoice::Req,\n) -> rejoice::Res {{\n",
-            route.mod_name, method
-        ));
+        "state.clone(), "
+    };
 
-        output.push_str("    let res = rejoice::Res::new();\n");
-        if stateless {
-            output.push_str(&format!(
-                "    let _ = state;\n    let res = routes::{}::{}(req.clone(), res).await;\n",
-                route.mod_name, method
-            ));
-        } else {
+    // Fragments are pieces of the GET page, rendered on their own without
+    // layouts
+    if method == "get" && !route.fragments.is_empty() {
+        output.push_str("    if let Some(fragment) = req.fragment() {\n");
+        output.push_str("        return match fragment.as_str() {\n");
+        for fragment in &route.fragments {
             output.push_str(&format!(
-                "    let res = routes::{}::{}(state.clone(), req.clone(), res).await;\n",
-                route.mod_name, method
+                "            {:?} => rejoice::IntoRes::into_res(routes::{}::fragment_{}({state_arg}req.clone(), res{param_arg}).await, &base).into_fragment(),\n",
+                fragment, route.mod_name, fragment
             ));
         }
+        output.push_str("            _ => res.not_found(\"Unknown fragment\"),\n");
+        output.push_str("        };\n    }\n");
     }
 
-    // Layout wrapping (only for HTML responses)
-    output.push_str("    if !res.is_html() { return res; }\n");
-    output.push_str("    let html_content = res.take_html().unwrap();\n");
-    output.push_str("    let children: rejoice::Children = rejoice::PreEscaped(html_content);\n");
+    output.push_str(&format!(
+        "    let res = rejoice::IntoRes::into_res(routes::{}::{}({state_arg}req.clone(), res{param_arg}).await, &base);\n",
+        route.mod_name, method
+    ));
+
+    if chain.is_empty() {
+        output.push_str("    res\n}");
+        return output;
+    }
+
+    // Layout wrapping (only for full-page HTML responses)
+    output.push_str("    if !res.is_html() || res.skip_layouts(&req) { return res; }\n");
+    output.push_str("    let children: rejoice::Children = res.layout_children();\n");
 
     for (i, layout_mod) in chain.iter().rev().enumerate() {
         let children_var = if i == 0 {
@@ -308,30 +405,80 @@ fn generate_wrapper_handler(
 
         if stateless {
             output.push_str(&format!(
-                "    let layout_res = routes::{}::layout(req.clone(), rejoice::Res::new(), {}).await;\n",
+                "    let layout_res = rejoice::IntoRes::into_res(routes::{}::layout(req.clone(), base.fresh(), {}).await, &base);\n",
                 layout_mod, children_var
             ));
         } else {
             output.push_str(&format!(
-                "    let layout_res = routes::{}::layout(state.clone(), req.clone(), rejoice::Res::new(), {}).await;\n",
+                "    let layout_res = rejoice::IntoRes::into_res(routes::{}::layout(state.clone(), req.clone(), base.fresh(), {}).await, &base);\n",
                 layout_mod, children_var
             ));
         }
 
+        // The page keeps its status, headers and cookies, so error pages
+        // keep their status once wrapped
         output.push_str("    if !layout_res.is_html() { return layout_res; }\n");
+        output.push_str("    res.absorb_layout(&layout_res);\n");
 
         if i < chain.len() - 1 {
             output.push_str(&format!(
-                "    let {}: rejoice::Children = rejoice::PreEscaped(layout_res.take_html().unwrap());\n",
+                "    let {}: rejoice::Children = res.layout_children();\n",
                 next_children_var
             ));
         } else {
-            output.push_str("    layout_res\n");
+            output.push_str("    res\n");
         }
     }
 
     output.push('}');
-    Some(output)
+    output
+}
+
+/// Generate the GET handler for a route file exporting `ws`.
+///
+/// WebSocket upgrades go to `ws`; other requests go to the file's `get`
+/// wrapper, or get a 400 if there isn't one.
+fn generate_ws_handler(route: &RouteInfo, stateless: bool) -> String {
+    let mut output = String::new();
+
+    let (param_extractor, param_arg, param_pattern) = match &route.param {
+        Some(param) => (
+            format!("    rejoice::Path({param}): rejoice::Path<String>,\n"),
+            format!(", {param}"),
+            format!("rejoice::Path({param}), "),
+        ),
+        None => (String::new(), String::new(), String::new()),
+    };
+    output.push_str(&format!(
+        "async fn wrapper_{}_ws(\n    rejoice::State(state): rejoice::State<__RejoiceState>,\n{param_extractor}    upgrade: rejoice::websocket::Upgrade,\n    res: rejoice::Res,\n    req: rejoice::Req,\n) -> rejoice::Response {{\n",
+        route.mod_name
+    ));
+
+    output.push_str("    let Some(upgrade) = upgrade.into_inner() else {\n");
+    if route.methods.iter().any(|m| m == "get") {
+        output.push_str(&format!(
+            "        return rejoice::IntoResponse::into_response(wrapper_{}_get(rejoice::State(state), {param_pattern}res, req).await);\n",
+            route.mod_name
+        ));
+    } else {
+        output.push_str("        let _ = (state, req);\n");
+        output.push_str(
+            "        return rejoice::IntoResponse::into_response(res.bad_request(\"Expected a WebSocket connection\"));\n",
+        );
+    }
+    output.push_str("    };\n");
+
+    let state_arg = if stateless { "" } else { "state, " };
+    if stateless {
+        output.push_str("    let _ = state;\n");
+    }
+    output.push_str("    let _ = res;\n");
+    output.push_str(&format!(
+        "    upgrade.on_upgrade(move |socket| async move {{\n        rejoice::websocket::HandlerOutcome::finish(routes::{}::ws({state_arg}rejoice::WebSocket::new(socket), req{param_arg}).await);\n    }})\n}}",
+        route.mod_name
+    ));
+
+    output
 }
 
 fn generate_routes_mod(base_dir: &Path, dir: &Path, mod_prefix: &str, output: &mut String) {
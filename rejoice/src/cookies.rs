@@ -0,0 +1,102 @@
+use base64::{Engine, engine::general_purpose::STANDARD};
+use cookie::{Cookie, CookieJar, Key};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+/// Length of a base64-encoded HMAC-SHA256 digest.
+const SIGNATURE_LEN: usize = 44;
+
+/// Secret keys used to sign and encrypt cookies.
+///
+/// New cookies are always written with the current key. Previous keys are
+/// only used for reading, so secrets can be rotated without logging everyone
+/// out.
+#[derive(Clone)]
+pub(crate) struct SecretKeys {
+    current: Key,
+    previous: Vec<Key>,
+}
+
+impl std::fmt::Debug for SecretKeys {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SecretKeys").finish_non_exhaustive()
+    }
+}
+
+impl SecretKeys {
+    pub fn new(secret: &[u8]) -> Self {
+        Self {
+            current: derive_key(secret),
+            previous: Vec::new(),
+        }
+    }
+
+    pub fn add_previous(&mut self, secret: &[u8]) {
+        self.previous.push(derive_key(secret));
+    }
+
+    fn all(&self) -> impl Iterator<Item = &Key> {
+        std::iter::once(&self.current).chain(self.previous.iter())
+    }
+
+    /// Sign a value, returning the string to store in the cookie.
+    ///
+    /// The signature covers the cookie name as well, so a signed value can't
+    /// be replayed under a different cookie.
+    pub fn sign(&self, name: &str, value: &str) -> String {
+        let signature = mac(&self.current, name, value).finalize().into_bytes();
+        format!("{}{}", STANDARD.encode(signature), value)
+    }
+
+    /// Encrypt a value, returning the string to store in the cookie.
+    pub fn encrypt(&self, name: &str, value: &str) -> String {
+        let mut jar = CookieJar::new();
+        jar.private_mut(&self.current)
+            .add(Cookie::new(name.to_string(), value.to_string()));
+        jar.get(name).map(|c| c.value().to_string()).unwrap_or_default()
+    }
+
+    /// Verify a signed cookie value with any known key.
+    pub fn verify(&self, name: &str, raw: &str) -> Option<String> {
+        if !raw.is_char_boundary(SIGNATURE_LEN) {
+            return None;
+        }
+        let (signature, value) = raw.split_at(SIGNATURE_LEN);
+        let signature = STANDARD.decode(signature).ok()?;
+
+        self.all()
+            .any(|key| mac(key, name, value).verify_slice(&signature).is_ok())
+            .then(|| value.to_string())
+    }
+
+    /// Decrypt and authenticate a private cookie value with any known key.
+    pub fn decrypt(&self, name: &str, raw: &str) -> Option<String> {
+        let jar = original_jar(name, raw);
+        self.all()
+            .find_map(|key| jar.private(key).get(name))
+            .map(|c| c.value().to_string())
+    }
+}
+
+fn derive_key(secret: &[u8]) -> Key {
+    assert!(
+        secret.len() >= 32,
+        "Cookie secret keys must be at least 32 bytes long"
+    );
+    Key::derive_from(secret)
+}
+
+fn mac(key: &Key, name: &str, value: &str) -> Hmac<Sha256> {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(key.signing()).expect("HMAC accepts any key length");
+    mac.update(name.as_bytes());
+    mac.update(b"=");
+    mac.update(value.as_bytes());
+    mac
+}
+
+fn original_jar(name: &str, raw: &str) -> CookieJar {
+    let mut jar = CookieJar::new();
+    jar.add_original(Cookie::new(name.to_string(), raw.to_string()));
+    jar
+}
@@ -0,0 +1,350 @@
+//! Cross-origin resource sharing.
+//!
+//! Browsers only let scripts on other origins call your app if it answers
+//! with CORS headers. Configure which origins may do so with `App::cors`:
+//!
+//! ```ignore
+//! use rejoice::cors::CorsConfig;
+//!
+//! let app = App::new(8080, create_router()).cors(
+//!     CorsConfig::default()
+//!         .allow_origin("https://app.example.com")
+//!         .allow_credentials(true)
+//!         .route("/api/public", CorsConfig::permissive()),
+//! );
+//! ```
+//!
+//! Without `App::cors`, every origin is allowed in development and none in
+//! production.
+
+use axum::{
+    body::Body,
+    http::{HeaderName, HeaderValue, Method, Request, Response, Uri},
+};
+use futures::future::BoxFuture;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tower::{Layer, Service};
+use tower_http::cors::{AllowHeaders, AllowMethods, AllowOrigin};
+
+/// Methods allowed unless `CorsConfig::allow_methods` says otherwise.
+const DEFAULT_METHODS: [Method; 6] = [
+    Method::GET,
+    Method::HEAD,
+    Method::POST,
+    Method::PUT,
+    Method::PATCH,
+    Method::DELETE,
+];
+
+/// CORS settings.
+///
+/// The default allows no origins, so it answers no cross-origin requests.
+/// Add origins with `allow_origin`, or start from `permissive` for a public
+/// API.
+#[derive(Debug, Clone)]
+pub struct CorsConfig {
+    /// Allowed origins, or `None` for any
+    origins: Option<Vec<HeaderValue>>,
+    /// Allowed methods, or `None` for whatever the browser asks for
+    methods: Option<Vec<Method>>,
+    /// Allowed request headers, or `None` for whatever the browser asks for
+    headers: Option<Vec<HeaderName>>,
+    expose_headers: Vec<HeaderName>,
+    credentials: bool,
+    max_age: Option<Duration>,
+    routes: Vec<(String, CorsConfig)>,
+}
+
+impl Default for CorsConfig {
+    fn default() -> Self {
+        Self {
+            origins: Some(Vec::new()),
+            methods: Some(DEFAULT_METHODS.to_vec()),
+            headers: None,
+            expose_headers: Vec::new(),
+            credentials: false,
+            max_age: None,
+            routes: Vec::new(),
+        }
+    }
+}
+
+impl CorsConfig {
+    /// Allow any origin, method and header, without credentials.
+    ///
+    /// This is what development uses when `App::cors` isn't called.
+    pub fn permissive() -> Self {
+        Self {
+            origins: None,
+            methods: None,
+            ..Self::default()
+        }
+    }
+
+    /// Allow requests from `origin`, e.g. `"https://app.example.com"`.
+    ///
+    /// # Panics
+    /// Panics if `origin` is not a scheme and host, or is `"*"`; use
+    /// `allow_any_origin` for that.
+    pub fn allow_origin(mut self, origin: &str) -> Self {
+        assert!(
+            origin != "*",
+            "\"*\" is not an origin; call CorsConfig::allow_any_origin"
+        );
+        let origin = origin.trim_end_matches('/');
+        let valid = origin.parse::<Uri>().is_ok_and(|uri| {
+            uri.scheme().is_some()
+                && uri.authority().is_some()
+                && uri.path_and_query().is_none_or(|p| p.as_str() == "/")
+        });
+        assert!(
+            valid,
+            "{:?} is not a valid origin, e.g. \"https://example.com\"",
+            origin
+        );
+        self.origins
+            .get_or_insert_with(Vec::new)
+            .push(HeaderValue::from_str(origin).expect("origin is a valid URI"));
+        self
+    }
+
+    /// Allow requests from any origin.
+    ///
+    /// Can't be combined with `allow_credentials`.
+    pub fn allow_any_origin(mut self) -> Self {
+        self.origins = None;
+        self
+    }
+
+    /// Allow only these methods (default GET, HEAD, POST, PUT, PATCH and
+    /// DELETE).
+    ///
+    /// # Panics
+    /// Panics if an entry is not a valid method.
+    pub fn allow_methods<I, S>(mut self, methods: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let methods = methods
+            .into_iter()
+            .map(|method| {
+                let method = method.as_ref();
+                Method::from_bytes(method.to_ascii_uppercase().as_bytes())
+                    .unwrap_or_else(|_| panic!("{:?} is not a valid method", method))
+            })
+            .collect();
+        self.methods = Some(methods);
+        self
+    }
+
+    /// Allow only these request headers. By default any header the browser
+    /// asks for is allowed.
+    ///
+    /// # Panics
+    /// Panics if an entry is not a valid header name.
+    pub fn allow_headers<I, S>(mut self, headers: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.headers = Some(header_names(headers));
+        self
+    }
+
+    /// Let scripts read these response headers, beyond the few browsers
+    /// always expose.
+    ///
+    /// # Panics
+    /// Panics if an entry is not a valid header name.
+    pub fn expose_headers<I, S>(mut self, headers: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        self.expose_headers.extend(header_names(headers));
+        self
+    }
+
+    /// Let cross-origin requests send and receive cookies (default `false`).
+    ///
+    /// Requires an explicit list of origins.
+    pub fn allow_credentials(mut self, allow: bool) -> Self {
+        self.credentials = allow;
+        self
+    }
+
+    /// How long browsers may cache the answer to a preflight request.
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    /// Use `config` instead for paths under `prefix`, e.g. `"/api"`.
+    ///
+    /// The longest matching prefix wins. Pass `CorsConfig::default()` to turn
+    /// CORS off under `prefix`.
+    ///
+    /// # Panics
+    /// Panics if `config` has overrides of its own.
+    pub fn route(mut self, prefix: impl Into<String>, config: CorsConfig) -> Self {
+        assert!(
+            config.routes.is_empty(),
+            "CORS overrides can't be nested; call CorsConfig::route on the top-level config"
+        );
+        self.routes.push((prefix.into(), config));
+        self
+    }
+
+    /// Check the settings, panicking with a hint if browsers would reject
+    /// them.
+    pub(crate) fn validate(&self) {
+        for config in std::iter::once(self).chain(self.routes.iter().map(|(_, c)| c)) {
+            assert!(
+                !(config.credentials && config.origins.is_none()),
+                "CORS credentials can't be combined with any origin; list the allowed origins with CorsConfig::allow_origin"
+            );
+        }
+    }
+
+    /// Whether any origin is allowed at all.
+    fn enabled(&self) -> bool {
+        self.origins
+            .as_ref()
+            .is_none_or(|origins| !origins.is_empty())
+    }
+
+    fn layer(&self) -> tower_http::cors::CorsLayer {
+        let origins = match &self.origins {
+            Some(origins) => AllowOrigin::list(origins.clone()),
+            None => AllowOrigin::any(),
+        };
+        let methods = match &self.methods {
+            Some(methods) => AllowMethods::list(methods.clone()),
+            None => AllowMethods::mirror_request(),
+        };
+        let headers = match &self.headers {
+            Some(headers) => AllowHeaders::list(headers.clone()),
+            None => AllowHeaders::mirror_request(),
+        };
+
+        let mut layer = tower_http::cors::CorsLayer::new()
+            .allow_origin(origins)
+            .allow_methods(methods)
+            .allow_headers(headers)
+            .expose_headers(self.expose_headers.clone())
+            .allow_credentials(self.credentials);
+        if let Some(max_age) = self.max_age {
+            layer = layer.max_age(max_age);
+        }
+        layer
+    }
+}
+
+fn header_names<I, S>(headers: I) -> Vec<HeaderName>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    headers
+        .into_iter()
+        .map(|name| {
+            let name = name.as_ref();
+            HeaderName::try_from(name)
+                .unwrap_or_else(|_| panic!("{:?} is not a valid header name", name))
+        })
+        .collect()
+}
+
+/// Whether `path` is `prefix` or below it.
+fn is_under(path: &str, prefix: &str) -> bool {
+    let prefix = prefix.trim_end_matches('/');
+    path == prefix
+        || path
+            .strip_prefix(prefix)
+            .is_some_and(|rest| rest.starts_with('/'))
+}
+
+#[derive(Clone)]
+pub(crate) struct CorsLayer {
+    pub config: Arc<CorsConfig>,
+}
+
+impl<S> Layer<S> for CorsLayer {
+    type Service = CorsMiddleware<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        let policy = |config: &CorsConfig| config.enabled().then(|| config.layer());
+        let routes = self
+            .config
+            .routes
+            .iter()
+            .map(|(prefix, config)| (prefix.clone(), policy(config)))
+            .collect();
+
+        CorsMiddleware {
+            inner,
+            policies: Arc::new(Policies {
+                default: policy(&self.config),
+                routes,
+            }),
+        }
+    }
+}
+
+/// The CORS handling for each path prefix, `None` where it's off.
+struct Policies {
+    default: Option<tower_http::cors::CorsLayer>,
+    routes: Vec<(String, Option<tower_http::cors::CorsLayer>)>,
+}
+
+impl Policies {
+    /// The policy for `path`, where the longest matching prefix wins.
+    fn for_path(&self, path: &str) -> Option<&tower_http::cors::CorsLayer> {
+        self.routes
+            .iter()
+            .filter(|(prefix, _)| is_under(path, prefix))
+            .max_by_key(|(prefix, _)| prefix.trim_end_matches('/').len())
+            .map_or(&self.default, |(_, policy)| policy)
+            .as_ref()
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct CorsMiddleware<S> {
+    inner: S,
+    policies: Arc<Policies>,
+}
+
+impl<S> Service<Request<Body>> for CorsMiddleware<S>
+where
+    S: Service<Request<Body>, Response = Response<Body>> + Clone + Send + 'static,
+    S::Future: Send,
+{
+    type Response = Response<Body>;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        // Call the instance `poll_ready` prepared, leaving a clone in its place
+        let clone = self.inner.clone();
+        let inner = std::mem::replace(&mut self.inner, clone);
+
+        match self.policies.for_path(req.uri().path()) {
+            Some(policy) => {
+                let mut cors = policy.layer(inner);
+                Box::pin(async move { cors.call(req).await })
+            }
+            None => {
+                let mut inner = inner;
+                Box::pin(async move { inner.call(req).await })
+            }
+        }
+    }
+}
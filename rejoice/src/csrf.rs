@@ -0,0 +1,559 @@
+//! Cross-site request forgery protection.
+//!
+//! Enable it with `App::csrf`. Every form that submits with POST, PUT, PATCH
+//! or DELETE must then include [`csrf_field`]; other clients can send the
+//! token in an `X-CSRF-Token` header instead. Tokens are stored in the
+//! session, so sessions must be enabled too.
+//!
+//! ```ignore
+//! let app = App::new(8080, create_router())
+//!     .sessions(MemoryStore::new())
+//!     .csrf(CsrfConfig::default().exempt("/api"));
+//! ```
+
+use crate::session::{Session, generate_id};
+use axum::{
+    body::{Body, Bytes},
+    http::{Method, Request, Response, header},
+    response::IntoResponse,
+};
+use colored::Colorize;
+use futures::{Stream, StreamExt, future, stream};
+use maud::{Markup, html};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tower::{Layer, Service};
+
+/// Session key holding the token.
+const SESSION_KEY: &str = "_csrf_token";
+
+/// Form field carrying the token.
+pub const FIELD_NAME: &str = "_csrf";
+
+/// Header carrying the token.
+pub const HEADER_NAME: &str = "x-csrf-token";
+
+tokio::task_local! {
+    static CURRENT_SESSION: Session;
+    /// Session of a streamed response, whose chunks render after the handler
+    /// has returned
+    static STREAM_SESSION: Session;
+}
+
+/// CSRF protection settings.
+#[derive(Debug, Clone, Default)]
+pub struct CsrfConfig {
+    exempt: Vec<String>,
+}
+
+impl CsrfConfig {
+    /// Skip token checks for paths under `prefix`, e.g. `"/api"` for JSON
+    /// endpoints that authenticate with headers rather than cookies.
+    pub fn exempt(mut self, prefix: impl Into<String>) -> Self {
+        self.exempt.push(prefix.into());
+        self
+    }
+
+    fn is_exempt(&self, path: &str) -> bool {
+        self.exempt.iter().any(|prefix| {
+            let prefix = prefix.trim_end_matches('/');
+            path == prefix
+                || path
+                    .strip_prefix(prefix)
+                    .is_some_and(|rest| rest.starts_with('/'))
+        })
+    }
+}
+
+/// Get the session's CSRF token, creating one if needed.
+pub(crate) fn token_for(session: &Session) -> String {
+    if let Some(token) = session.get::<String>(SESSION_KEY) {
+        return token;
+    }
+    let token = generate_id();
+    session.insert(SESSION_KEY, &token);
+    token
+}
+
+/// The CSRF token for the current request.
+///
+/// # Panics
+/// Panics if called outside a request handler or if CSRF protection isn't
+/// enabled with `App::csrf`.
+pub fn csrf_token() -> String {
+    if let Ok(token) = STREAM_SESSION.try_with(stream_token) {
+        return token;
+    }
+    CURRENT_SESSION
+        .try_with(token_for)
+        .expect("CSRF protection is not enabled; call App::csrf")
+}
+
+/// Get the token while a streamed response renders.
+///
+/// The session cookie has been sent by then, so a token created for a new
+/// session can't be saved.
+fn stream_token(session: &Session) -> String {
+    if session.id().is_none()
+        && !session.contains(SESSION_KEY)
+        && std::env::var("REJOICE_DEV").is_ok()
+    {
+        eprintln!(
+            "{} csrf_field() rendered in a stream for a new session, so its token can't be saved; call req.csrf_token() before res.stream",
+            "warning:".yellow().bold()
+        );
+    }
+    token_for(session)
+}
+
+/// Give `chunks`, which are rendered after the handler has returned, access
+/// to the current request's session.
+///
+/// A token is only created if a chunk renders one; the session middleware
+/// saves it once the stream ends.
+pub(crate) fn with_session<S>(chunks: S) -> impl Stream<Item = S::Item> + Send + 'static
+where
+    S: Stream + Send + 'static,
+{
+    let session = CURRENT_SESSION.try_with(Clone::clone).ok();
+    let mut chunks = Box::pin(chunks);
+    stream::poll_fn(move |cx| match &session {
+        Some(session) => {
+            STREAM_SESSION.sync_scope(session.clone(), || chunks.as_mut().poll_next(cx))
+        }
+        None => chunks.as_mut().poll_next(cx),
+    })
+}
+
+/// Render a hidden input carrying the CSRF token.
+///
+/// # Example
+/// ```ignore
+/// html! {
+///     form method="post" action="/posts" {
+///         (csrf_field())
+///         input name="title";
+///         button { "Create" }
+///     }
+/// }
+/// ```
+///
+/// # Panics
+/// Panics if CSRF protection isn't enabled with `App::csrf`.
+pub fn csrf_field() -> Markup {
+    html! {
+        input type="hidden" name=(FIELD_NAME) value=(csrf_token());
+    }
+}
+
+/// Render a `<meta name="csrf-token">` tag for JavaScript clients, which
+/// should send the value back in an `X-CSRF-Token` header.
+///
+/// # Panics
+/// Panics if CSRF protection isn't enabled with `App::csrf`.
+pub fn csrf_meta() -> Markup {
+    html! {
+        meta name="csrf-token" content=(csrf_token());
+    }
+}
+
+/// Compare tokens in constant time.
+fn tokens_match(a: &str, b: &str) -> bool {
+    a.len() == b.len() && a.bytes().zip(b.bytes()).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+fn is_safe_method(method: &Method) -> bool {
+    matches!(
+        *method,
+        Method::GET | Method::HEAD | Method::OPTIONS | Method::TRACE
+    )
+}
+
+#[derive(Clone)]
+pub(crate) struct CsrfLayer {
+    pub config: Arc<CsrfConfig>,
+}
+
+impl<S> Layer<S> for CsrfLayer {
+    type Service = CsrfMiddleware<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        CsrfMiddleware {
+            inner,
+            config: self.config.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct CsrfMiddleware<S> {
+    inner: S,
+    config: Arc<CsrfConfig>,
+}
+
+impl<S> Service<Request<Body>> for CsrfMiddleware<S>
+where
+    S: Service<Request<Body>, Response = Response<Body>> + Clone + Send + 'static,
+    S::Future: Send,
+{
+    type Response = Response<Body>;
+    type Error = S::Error;
+    type Future = std::pin::Pin<
+        Box<dyn std::future::Future<Output = Result<Self::Response, Self::Error>> + Send>,
+    >;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        let mut inner = self.inner.clone();
+        let config = self.config.clone();
+
+        Box::pin(async move {
+            let session = req
+                .extensions()
+                .get::<Session>()
+                .cloned()
+                .expect("CSRF protection requires sessions; call App::sessions");
+
+            let needs_check =
+                !is_safe_method(req.method()) && !config.is_exempt(req.uri().path());
+
+            let req = if needs_check {
+                let (submitted, req) = submitted_token(req).await;
+                let expected = session.get::<String>(SESSION_KEY);
+
+                let valid = matches!(
+                    (&submitted, &expected),
+                    (Some(submitted), Some(expected)) if tokens_match(submitted, expected)
+                );
+                if !valid {
+                    return Ok(rejection(req));
+                }
+                req
+            } else {
+                req
+            };
+
+            CURRENT_SESSION.scope(session, inner.call(req)).await
+        })
+    }
+}
+
+/// Read the submitted token from the header or the form body.
+///
+/// Reading the form consumes the body, so the request is rebuilt with the
+/// buffered bytes for the handler.
+async fn submitted_token(req: Request<Body>) -> (Option<String>, Request<Body>) {
+    if let Some(token) = req
+        .headers()
+        .get(HEADER_NAME)
+        .and_then(|v| v.to_str().ok())
+    {
+        return (Some(token.to_string()), req);
+    }
+
+    let content_type = req
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default()
+        .to_string();
+    if let Some(boundary) = multipart_boundary(&content_type) {
+        return multipart_token(req, boundary).await;
+    }
+    if !content_type.starts_with("application/x-www-form-urlencoded") {
+        return (None, req);
+    }
+
+    let (parts, body) = req.into_parts();
+    let bytes = axum::body::to_bytes(body, usize::MAX)
+        .await
+        .unwrap_or_default();
+    let token = serde_urlencoded::from_bytes::<HashMap<String, String>>(&bytes)
+        .ok()
+        .and_then(|mut fields| fields.remove(FIELD_NAME));
+
+    (token, Request::from_parts(parts, Body::from(bytes)))
+}
+
+/// The boundary of a `multipart/form-data` content type.
+fn multipart_boundary(content_type: &str) -> Option<&str> {
+    let (mime, params) = content_type.split_once(';')?;
+    if !mime.trim().eq_ignore_ascii_case("multipart/form-data") {
+        return None;
+    }
+    params
+        .split(';')
+        .find_map(|param| param.trim().strip_prefix("boundary="))
+        .map(|boundary| boundary.trim_matches('"'))
+        .filter(|boundary| !boundary.is_empty())
+}
+
+/// Read the token from a multipart body.
+///
+/// Only the body up to the end of the token's part is buffered; the rest,
+/// such as files after it, streams through to the handler.
+async fn multipart_token(req: Request<Body>, boundary: &str) -> (Option<String>, Request<Body>) {
+    let (parts, body) = req.into_parts();
+    let mut chunks = body.into_data_stream().fuse();
+    let mut scan = MultipartScan::new(boundary);
+    let mut read = Vec::new();
+
+    let token = loop {
+        match chunks.next().await {
+            Some(Ok(chunk)) => {
+                read.extend_from_slice(&chunk);
+                if let Some(token) = scan.scan(&read) {
+                    break token;
+                }
+            }
+            Some(Err(_)) | None => break None,
+        }
+    };
+
+    let read = stream::once(future::ready(Ok(Bytes::from(read))));
+    let body = Body::from_stream(read.chain(chunks));
+    (token, Request::from_parts(parts, body))
+}
+
+/// Incremental search for the token in a `multipart/form-data` body, which
+/// arrives in chunks.
+struct MultipartScan {
+    /// `\r\n--` followed by the boundary, which ends each part
+    delimiter: Vec<u8>,
+    /// Where the part being read starts, just after its delimiter
+    part: Option<usize>,
+    /// Where to resume looking for the end of that part
+    resume: usize,
+}
+
+impl MultipartScan {
+    fn new(boundary: &str) -> Self {
+        Self {
+            delimiter: format!("\r\n--{}", boundary).into_bytes(),
+            part: None,
+            resume: 0,
+        }
+    }
+
+    /// Look for the token in the body read so far, returning `None` if more
+    /// is needed and `Some(None)` if the body has no token.
+    fn scan(&mut self, body: &[u8]) -> Option<Option<String>> {
+        let mut start = match self.part {
+            Some(start) => start,
+            // The first delimiter isn't preceded by a line break
+            None => find(body, &self.delimiter[2..], 0)? + self.delimiter.len() - 2,
+        };
+
+        loop {
+            self.part = Some(start);
+            // The last delimiter is followed by `--`
+            if body.get(start..start + 2)? == b"--" {
+                return Some(None);
+            }
+            let headers_end = find(body, b"\r\n\r\n", start)?;
+            let content_start = headers_end + 4;
+            let Some(content_end) = find(body, &self.delimiter, self.resume.max(content_start))
+            else {
+                // The delimiter may be cut off at the end of what was read
+                self.resume = body.len().saturating_sub(self.delimiter.len() - 1);
+                return None;
+            };
+
+            if part_name(&body[start..headers_end]).as_deref() == Some(FIELD_NAME) {
+                let token = String::from_utf8_lossy(&body[content_start..content_end]);
+                return Some(Some(token.into_owned()));
+            }
+            start = content_end + self.delimiter.len();
+            self.resume = 0;
+        }
+    }
+}
+
+/// The position of `needle` in `haystack`, starting at `from`.
+fn find(haystack: &[u8], needle: &[u8], from: usize) -> Option<usize> {
+    haystack
+        .get(from..)?
+        .windows(needle.len())
+        .position(|window| window == needle)
+        .map(|i| i + from)
+}
+
+/// The field name in a part's `Content-Disposition` header.
+fn part_name(headers: &[u8]) -> Option<String> {
+    let headers = std::str::from_utf8(headers).ok()?;
+    headers.lines().find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        if !name.trim().eq_ignore_ascii_case("content-disposition") {
+            return None;
+        }
+        value
+            .split(';')
+            .find_map(|param| param.trim().strip_prefix("name="))
+            .map(|name| name.trim_matches('"').to_string())
+    })
+}
+
+fn rejection(req: Request<Body>) -> Response<Body> {
+    let (parts, _) = req.into_parts();
+    crate::Res::for_request(&parts)
+        .forbidden("Invalid or missing CSRF token")
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Res;
+    use crate::session::{MemoryStore, SessionConfig, SessionLayer, SessionStore};
+    use axum::{Router, routing::get};
+    use std::time::{Duration, SystemTime};
+    use tower::ServiceExt;
+
+    fn streaming_app(store: MemoryStore, with_field: bool) -> Router {
+        Router::new()
+            .route(
+                "/",
+                get(move || async move {
+                    Res::new().stream(stream::once(async move {
+                        if with_field {
+                            csrf_field()
+                        } else {
+                            html! { p { "Hi" } }
+                        }
+                    }))
+                }),
+            )
+            .layer(CsrfLayer {
+                config: Arc::new(CsrfConfig::default()),
+            })
+            .layer(SessionLayer {
+                store: Arc::new(store),
+                config: Arc::new(SessionConfig::default()),
+            })
+    }
+
+    #[tokio::test]
+    async fn csrf_field_renders_in_streamed_chunks() {
+        let session = Session::new(None, Default::default());
+        let res = CURRENT_SESSION
+            .scope(session.clone(), async {
+                Res::new().stream(stream::once(async { csrf_field() }))
+            })
+            .await;
+
+        // The chunk is only rendered now, outside the handler's scope
+        let body = axum::body::to_bytes(res.into_response().into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let token = session.get::<String>(SESSION_KEY).unwrap();
+        assert_eq!(
+            String::from_utf8_lossy(&body),
+            format!(r#"<input type="hidden" name="_csrf" value="{}">"#, token)
+        );
+    }
+
+    #[tokio::test]
+    async fn stream_without_csrf_field_sets_no_session_cookie() {
+        let app = streaming_app(MemoryStore::new(), false);
+        let res = app
+            .oneshot(Request::get("/").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+
+        assert!(res.headers().get(header::SET_COOKIE).is_none());
+        axum::body::to_bytes(res.into_body(), usize::MAX)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn token_rendered_in_stream_is_saved_to_existing_session() {
+        let store = MemoryStore::new();
+        let expires_at = SystemTime::now() + Duration::from_secs(60);
+        store
+            .save("existing", &Default::default(), expires_at)
+            .await
+            .unwrap();
+
+        let app = streaming_app(store.clone(), true);
+        let res = app
+            .oneshot(
+                Request::get("/")
+                    .header(header::COOKIE, "rejoice_session=existing")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert!(res.headers().get(header::SET_COOKIE).is_none());
+
+        let body = axum::body::to_bytes(res.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let data = store.load("existing").await.unwrap().unwrap();
+        let token = data[SESSION_KEY].as_str().unwrap();
+        assert_eq!(
+            String::from_utf8_lossy(&body),
+            format!(r#"<input type="hidden" name="_csrf" value="{}">"#, token)
+        );
+    }
+
+    fn multipart_request(body: &'static str) -> Request<Body> {
+        // Deliver the body in small chunks, as a slow upload would
+        let chunks = body
+            .as_bytes()
+            .chunks(5)
+            .map(|chunk| Ok::<_, std::convert::Infallible>(Bytes::from_static(chunk)));
+        Request::post("/")
+            .header(header::CONTENT_TYPE, "multipart/form-data; boundary=XyZ")
+            .body(Body::from_stream(stream::iter(chunks)))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn token_is_read_from_multipart_body() {
+        let body = "--XyZ\r\n\
+            Content-Disposition: form-data; name=\"title\"\r\n\r\n\
+            Hello\r\n\
+            --XyZ\r\n\
+            Content-Disposition: form-data; name=\"file\"; filename=\"a.txt\"\r\n\
+            Content-Type: text/plain\r\n\r\n\
+            name=\"_csrf\"\r\n--Xy\r\n\
+            --XyZ\r\n\
+            Content-Disposition: form-data; name=\"_csrf\"\r\n\r\n\
+            secret\r\n\
+            --XyZ\r\n\
+            Content-Disposition: form-data; name=\"notes\"\r\n\r\n\
+            More\r\n\
+            --XyZ--\r\n";
+
+        let (token, req) = submitted_token(multipart_request(body)).await;
+        assert_eq!(token.as_deref(), Some("secret"));
+
+        // The handler still gets the whole body
+        let rest = axum::body::to_bytes(req.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(rest, body.as_bytes());
+    }
+
+    #[tokio::test]
+    async fn multipart_body_without_token_has_none() {
+        let body = "--XyZ\r\n\
+            Content-Disposition: form-data; name=\"title\"\r\n\r\n\
+            Hello\r\n\
+            --XyZ--\r\n";
+
+        let (token, req) = submitted_token(multipart_request(body)).await;
+        assert_eq!(token, None);
+
+        let rest = axum::body::to_bytes(req.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(rest, body.as_bytes());
+    }
+}
@@ -0,0 +1,337 @@
+use crate::Res;
+use axum::http::StatusCode;
+use colored::Colorize;
+use maud::{Markup, html};
+use std::backtrace::{Backtrace, BacktraceStatus};
+use std::error::Error as StdError;
+use std::fmt;
+
+/// Application error for handlers that return `Result<Res, rejoice::Error>`.
+///
+/// Any error type converts into it with `?`, so fallible calls no longer need
+/// `.unwrap()`. Errors default to 500 Internal Server Error; with the
+/// `sqlite` feature, `sqlx::Error::RowNotFound` becomes 404 Not Found.
+///
+/// # Example
+/// ```ignore
+/// pub async fn get(state: AppState, req: Req, res: Res, id: String) -> Result<Res, Error> {
+///     let id: i64 = id.parse().map_err(|_| Error::not_found("No such post"))?;
+///     let post = query_as::<_, Post>("SELECT * FROM posts WHERE id = ?")
+///         .bind(id)
+///         .fetch_one(&state.db)
+///         .await?;
+///     Ok(res.html(html! { h1 { (post.title) } }))
+/// }
+/// ```
+pub struct Error {
+    status: StatusCode,
+    message: Option<String>,
+    source: Box<dyn StdError + Send + Sync>,
+    backtrace: Option<Backtrace>,
+}
+
+impl Error {
+    /// Create an error with a status code and a message shown to the user.
+    pub fn new(status: StatusCode, message: impl Into<String>) -> Self {
+        let message = message.into();
+        let mut error = Self::from_boxed(Box::new(Message(message.clone())));
+        error.status = status;
+        error.message = Some(message);
+        error
+    }
+
+    /// Create a 500 Internal Server Error from a message.
+    pub fn msg(message: impl Into<String>) -> Self {
+        Self::from_boxed(Box::new(Message(message.into())))
+    }
+
+    /// Create a 400 Bad Request error.
+    pub fn bad_request(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, message)
+    }
+
+    /// Create a 401 Unauthorized error.
+    pub fn unauthorized(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::UNAUTHORIZED, message)
+    }
+
+    /// Create a 403 Forbidden error.
+    pub fn forbidden(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::FORBIDDEN, message)
+    }
+
+    /// Create a 404 Not Found error.
+    pub fn not_found(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::NOT_FOUND, message)
+    }
+
+    /// Change the status code the error is rendered with.
+    pub fn with_status(mut self, status: StatusCode) -> Self {
+        self.status = status;
+        self
+    }
+
+    /// The status code the error is rendered with.
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    /// The underlying error.
+    pub fn source(&self) -> &(dyn StdError + Send + Sync + 'static) {
+        &*self.source
+    }
+
+    fn from_boxed(source: Box<dyn StdError + Send + Sync>) -> Self {
+        #[allow(unused_mut)]
+        let mut status = StatusCode::INTERNAL_SERVER_ERROR;
+
+        #[cfg(feature = "sqlite")]
+        if let Some(sqlx::Error::RowNotFound) = source.downcast_ref::<sqlx::Error>() {
+            status = StatusCode::NOT_FOUND;
+        }
+
+        // Backtraces are only shown on the dev error page, so don't pay for
+        // them in production
+        let backtrace = std::env::var("REJOICE_DEV")
+            .is_ok()
+            .then(Backtrace::force_capture);
+
+        Self {
+            status,
+            message: None,
+            source,
+            backtrace,
+        }
+    }
+
+    /// The message shown to users outside dev mode.
+    ///
+    /// Server errors hide their details, since they may leak internals.
+    fn public_message(&self) -> String {
+        match &self.message {
+            Some(message) => message.clone(),
+            None if self.status.is_server_error() => "Something went wrong".to_string(),
+            None => self.source.to_string(),
+        }
+    }
+
+    fn chain(&self) -> Vec<String> {
+        let mut chain = vec![self.source.to_string()];
+        let mut current = self.source.source();
+        while let Some(cause) = current {
+            chain.push(cause.to_string());
+            current = cause.source();
+        }
+        chain
+    }
+}
+
+impl<E> From<E> for Error
+where
+    E: StdError + Send + Sync + 'static,
+{
+    fn from(error: E) -> Self {
+        Self::from_boxed(Box::new(error))
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.message {
+            Some(message) => f.write_str(message),
+            None => fmt::Display::fmt(&self.source, f),
+        }
+    }
+}
+
+impl fmt::Debug for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Error")
+            .field("status", &self.status)
+            .field("source", &self.source)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Error message without an underlying error type.
+#[derive(Debug)]
+struct Message(String);
+
+impl fmt::Display for Message {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl StdError for Message {}
+
+/// Renders the error responses of the `Res` error helpers, `Error` and CSRF
+/// rejections.
+///
+/// Register an implementation with `App::error_pages` to give error pages the
+/// site's branding. Every method has a default, so override only what you
+/// need: `page` covers every status, and the per-status methods take
+/// precedence over it. HTML pages go through the route's layouts like any
+/// other response. Clients that prefer JSON get `json` instead.
+///
+/// # Example
+/// ```ignore
+/// struct Pages;
+///
+/// impl ErrorPages for Pages {
+///     fn page(&self, status: StatusCode, message: &str) -> Markup {
+///         html! {
+///             div class="error" {
+///                 h1 { (status.as_u16()) }
+///                 p { (message) }
+///             }
+///         }
+///     }
+///
+///     fn not_found(&self, _message: &str) -> Markup {
+///         html! { h1 { "Nothing here" } a href="/" { "Back home" } }
+///     }
+/// }
+///
+/// let app = App::new(8080, create_router()).error_pages(Pages);
+/// ```
+pub trait ErrorPages: Send + Sync + 'static {
+    /// Page for any error status without a more specific method.
+    fn page(&self, status: StatusCode, message: &str) -> Markup {
+        html! {
+            h1 { (status.canonical_reason().unwrap_or("Error")) }
+            p { (message) }
+        }
+    }
+
+    /// Page for 400 Bad Request.
+    fn bad_request(&self, message: &str) -> Markup {
+        self.page(StatusCode::BAD_REQUEST, message)
+    }
+
+    /// Page for 401 Unauthorized.
+    fn unauthorized(&self, message: &str) -> Markup {
+        self.page(StatusCode::UNAUTHORIZED, message)
+    }
+
+    /// Page for 403 Forbidden.
+    fn forbidden(&self, message: &str) -> Markup {
+        self.page(StatusCode::FORBIDDEN, message)
+    }
+
+    /// Page for 404 Not Found.
+    fn not_found(&self, message: &str) -> Markup {
+        self.page(StatusCode::NOT_FOUND, message)
+    }
+
+    /// Page for 500 Internal Server Error.
+    fn internal_error(&self, message: &str) -> Markup {
+        self.page(StatusCode::INTERNAL_SERVER_ERROR, message)
+    }
+
+    /// Body for clients that prefer JSON, `{ "error": message }` by default.
+    fn json(&self, _status: StatusCode, message: &str) -> serde_json::Value {
+        serde_json::json!({ "error": message })
+    }
+}
+
+impl fmt::Debug for dyn ErrorPages {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ErrorPages")
+    }
+}
+
+/// The built-in pages: a heading and the message.
+pub(crate) struct DefaultErrorPages;
+
+impl ErrorPages for DefaultErrorPages {}
+
+/// Render the HTML page for `status` with the matching method.
+pub(crate) fn render_page(pages: &dyn ErrorPages, status: StatusCode, message: &str) -> Markup {
+    match status {
+        StatusCode::BAD_REQUEST => pages.bad_request(message),
+        StatusCode::UNAUTHORIZED => pages.unauthorized(message),
+        StatusCode::FORBIDDEN => pages.forbidden(message),
+        StatusCode::NOT_FOUND => pages.not_found(message),
+        StatusCode::INTERNAL_SERVER_ERROR => pages.internal_error(message),
+        _ => pages.page(status, message),
+    }
+}
+
+/// Errors that handlers can return in `Result<Res, E>`.
+///
+/// The error page goes through the route's layouts like any other HTML
+/// response.
+pub trait IntoErrorResponse {
+    /// Render the error using a fresh response for the same request.
+    fn into_error_response(self, res: Res) -> Res;
+}
+
+impl IntoErrorResponse for Error {
+    fn into_error_response(self, res: Res) -> Res {
+        let dev_mode = std::env::var("REJOICE_DEV").is_ok();
+
+        if self.status.is_server_error() {
+            eprintln!("{} {}", "error:".red().bold(), self.chain().join(": "));
+        }
+
+        if !dev_mode {
+            return res.error(self.status, &self.public_message());
+        }
+
+        // API clients get the full chain in the JSON body instead of the page
+        if res.prefers_json() {
+            return res.error(self.status, &self.chain().join(": "));
+        }
+
+        res.set_status(self.status);
+        let title = self.status.canonical_reason().unwrap_or("Error");
+        let backtrace = self
+            .backtrace
+            .as_ref()
+            .filter(|b| b.status() == BacktraceStatus::Captured)
+            .map(|b| b.to_string());
+
+        res.html(html! {
+            div style="font-family: ui-monospace, monospace; padding: 1rem;" {
+                h1 { (self.status.as_u16()) " " (title) }
+                @for (i, cause) in self.chain().iter().enumerate() {
+                    p {
+                        @if i > 0 { "Caused by: " }
+                        (cause)
+                    }
+                }
+                @if let Some(backtrace) = backtrace {
+                    pre style="overflow-x: auto; font-size: 0.8rem;" { (backtrace) }
+                }
+            }
+        })
+    }
+}
+
+/// Return an error response directly, e.g. `Err(res.not_found("No such post"))`.
+impl IntoErrorResponse for Res {
+    fn into_error_response(self, _res: Res) -> Res {
+        self
+    }
+}
+
+/// Handler return types: `Res` or `Result<Res, E>`.
+///
+/// Used by generated route wrappers.
+#[doc(hidden)]
+pub trait IntoRes {
+    fn into_res(self, base: &Res) -> Res;
+}
+
+impl IntoRes for Res {
+    fn into_res(self, _base: &Res) -> Res {
+        self
+    }
+}
+
+impl<E: IntoErrorResponse> IntoRes for Result<Res, E> {
+    fn into_res(self, base: &Res) -> Res {
+        self.unwrap_or_else(|error| error.into_error_response(base.fresh()))
+    }
+}
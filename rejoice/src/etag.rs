@@ -0,0 +1,130 @@
+//! Conditional GET for rendered responses.
+//!
+//! Enabled with `App::etags`. Successful HTML and JSON responses get a weak
+//! `ETag` computed from the final body, and requests whose cached copy is
+//! still current get an empty 304 Not Modified instead.
+
+use crate::files::{self, Validators};
+use crate::stream::Streaming;
+use axum::{
+    body::Body,
+    http::{HeaderMap, Method, Request, Response, StatusCode, header},
+};
+use sha2::{Digest, Sha256};
+use std::task::{Context, Poll};
+use tower::{Layer, Service};
+
+/// Compute a weak ETag from a response body.
+fn etag_for(body: &[u8]) -> String {
+    let digest = Sha256::digest(body);
+    let hex: String = digest[..12].iter().map(|b| format!("{:02x}", b)).collect();
+    format!("W/\"{}\"", hex)
+}
+
+fn is_html_or_json(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("text/html") || v.starts_with("application/json"))
+}
+
+fn validators(headers: &HeaderMap) -> Validators {
+    Validators {
+        etag: headers
+            .get(header::ETAG)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string),
+        last_modified: headers
+            .get(header::LAST_MODIFIED)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| httpdate::parse_http_date(v).ok()),
+    }
+}
+
+/// Turn a response into a 304, keeping the headers a cache needs to update
+/// its stored copy.
+fn not_modified(response: Response<Body>) -> Response<Body> {
+    let (mut parts, _) = response.into_parts();
+    parts.status = StatusCode::NOT_MODIFIED;
+    parts.headers.remove(header::CONTENT_TYPE);
+    parts.headers.remove(header::CONTENT_LENGTH);
+    Response::from_parts(parts, Body::empty())
+}
+
+#[derive(Clone)]
+pub(crate) struct ETagLayer;
+
+impl<S> Layer<S> for ETagLayer {
+    type Service = ETagMiddleware<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ETagMiddleware { inner }
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct ETagMiddleware<S> {
+    inner: S,
+}
+
+impl<S> Service<Request<Body>> for ETagMiddleware<S>
+where
+    S: Service<Request<Body>, Response = Response<Body>> + Clone + Send + 'static,
+    S::Future: Send,
+{
+    type Response = Response<Body>;
+    type Error = S::Error;
+    type Future = std::pin::Pin<
+        Box<dyn std::future::Future<Output = Result<Self::Response, Self::Error>> + Send>,
+    >;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        let mut inner = self.inner.clone();
+        let is_get = matches!(*req.method(), Method::GET | Method::HEAD);
+        let request_headers = req.headers().clone();
+
+        Box::pin(async move {
+            let response = inner.call(req).await?;
+
+            if !is_get || response.status() != StatusCode::OK {
+                return Ok(response);
+            }
+
+            let has_validators = response.headers().contains_key(header::ETAG)
+                || response.headers().contains_key(header::LAST_MODIFIED);
+
+            // Streamed bodies would have to be buffered to hash them, which
+            // defeats streaming; only explicit validators apply to them
+            let hashable = is_html_or_json(response.headers())
+                && !response.headers().contains_key(header::ETAG)
+                && response.extensions().get::<Streaming>().is_none();
+
+            if !hashable {
+                if has_validators
+                    && files::is_not_modified(&request_headers, &validators(response.headers()))
+                {
+                    return Ok(not_modified(response));
+                }
+                return Ok(response);
+            }
+
+            let (mut parts, body) = response.into_parts();
+            let bytes = axum::body::to_bytes(body, usize::MAX)
+                .await
+                .unwrap_or_default();
+            if let Ok(etag) = etag_for(&bytes).parse() {
+                parts.headers.insert(header::ETAG, etag);
+            }
+            let response = Response::from_parts(parts, Body::from(bytes));
+
+            if files::is_not_modified(&request_headers, &validators(response.headers())) {
+                return Ok(not_modified(response));
+            }
+            Ok(response)
+        })
+    }
+}
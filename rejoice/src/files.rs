@@ -0,0 +1,214 @@
+//! Helpers for file responses: content types, `Content-Disposition`,
+//! conditional requests and byte ranges.
+
+use axum::http::{HeaderMap, Method, header};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Guess a content type from a file name's extension.
+pub(crate) fn content_type_for(filename: &str) -> String {
+    let mime = mime_guess::from_path(filename).first_or_octet_stream();
+    if mime.type_() == mime_guess::mime::TEXT {
+        format!("{}; charset=utf-8", mime.essence_str())
+    } else {
+        mime.essence_str().to_string()
+    }
+}
+
+/// Build a `Content-Disposition` value for `filename`.
+///
+/// Browsers that understand RFC 6266 use the UTF-8 `filename*` parameter;
+/// older ones fall back to an ASCII approximation in `filename`.
+pub(crate) fn content_disposition(disposition: &str, filename: &str) -> String {
+    // Only the final path component is a file name
+    let filename = filename.rsplit(['/', '\\']).next().unwrap_or(filename);
+
+    let fallback: String = filename
+        .chars()
+        .map(|c| {
+            if (c.is_ascii_graphic() && c != '"' && c != '\\') || c == ' ' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+
+    if fallback == filename {
+        return format!("{}; filename=\"{}\"", disposition, filename);
+    }
+
+    let mut encoded = String::new();
+    for byte in filename.bytes() {
+        // attr-char from RFC 5987
+        if byte.is_ascii_alphanumeric() || b"!#$&+-.^_`|~".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    format!(
+        "{}; filename=\"{}\"; filename*=UTF-8''{}",
+        disposition, fallback, encoded
+    )
+}
+
+/// Validators describing the current version of a resource.
+pub(crate) struct Validators {
+    pub etag: Option<String>,
+    pub last_modified: Option<SystemTime>,
+}
+
+impl Validators {
+    /// Derive validators from a file's size and modification time.
+    pub fn for_file(len: u64, modified: Option<SystemTime>) -> Self {
+        let nanos = modified
+            .and_then(|m| m.duration_since(UNIX_EPOCH).ok())
+            .map(|d| d.as_nanos())
+            .unwrap_or_default();
+        Self {
+            etag: Some(format!("\"{:x}-{:x}\"", len, nanos)),
+            last_modified: modified.map(truncate_to_secs),
+        }
+    }
+}
+
+/// HTTP dates have one-second precision.
+pub(crate) fn truncate_to_secs(time: SystemTime) -> SystemTime {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    UNIX_EPOCH + Duration::from_secs(secs)
+}
+
+/// What part of a resource to send.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Selection {
+    Full,
+    /// Inclusive byte range
+    Partial(u64, u64),
+    Unsatisfiable,
+    NotModified,
+}
+
+/// Decide how to answer a request for a resource of `len` bytes, honoring
+/// `If-None-Match`, `If-Modified-Since`, `Range` and `If-Range`.
+pub(crate) fn select(
+    headers: &HeaderMap,
+    method: &Method,
+    len: u64,
+    validators: Option<&Validators>,
+) -> Selection {
+    if *method != Method::GET && *method != Method::HEAD {
+        return Selection::Full;
+    }
+
+    if let Some(validators) = validators
+        && is_not_modified(headers, validators)
+    {
+        return Selection::NotModified;
+    }
+
+    let Some(range) = header_str(headers, header::RANGE) else {
+        return Selection::Full;
+    };
+
+    // A stale If-Range means the client's partial copy is outdated, so it
+    // needs the whole thing
+    if let Some(if_range) = header_str(headers, header::IF_RANGE)
+        && !if_range_matches(if_range, validators)
+    {
+        return Selection::Full;
+    }
+
+    parse_range(range, len)
+}
+
+fn header_str(headers: &HeaderMap, name: header::HeaderName) -> Option<&str> {
+    headers.get(name).and_then(|v| v.to_str().ok())
+}
+
+/// Whether the client's cached copy, described by `If-None-Match` or
+/// `If-Modified-Since`, is still current.
+pub(crate) fn is_not_modified(headers: &HeaderMap, validators: &Validators) -> bool {
+    // If-None-Match takes precedence over If-Modified-Since
+    if let Some(if_none_match) = header_str(headers, header::IF_NONE_MATCH) {
+        return validators
+            .etag
+            .as_deref()
+            .is_some_and(|etag| etag_list_matches(if_none_match, etag));
+    }
+
+    match (
+        header_str(headers, header::IF_MODIFIED_SINCE).and_then(|v| httpdate::parse_http_date(v).ok()),
+        validators.last_modified,
+    ) {
+        (Some(since), Some(modified)) => modified <= since,
+        _ => false,
+    }
+}
+
+/// Weak comparison of an `If-None-Match` list against an ETag.
+fn etag_list_matches(list: &str, etag: &str) -> bool {
+    let etag = etag.trim_start_matches("W/");
+    list.split(',').map(str::trim).any(|candidate| {
+        candidate == "*" || candidate.trim_start_matches("W/") == etag
+    })
+}
+
+fn if_range_matches(if_range: &str, validators: Option<&Validators>) -> bool {
+    let Some(validators) = validators else {
+        return false;
+    };
+    let if_range = if_range.trim();
+
+    // If-Range requires a strong comparison, so weak tags never match
+    if if_range.starts_with('"') {
+        return validators.etag.as_deref() == Some(if_range);
+    }
+
+    match (httpdate::parse_http_date(if_range), validators.last_modified) {
+        (Ok(date), Some(modified)) => date == modified,
+        _ => false,
+    }
+}
+
+/// Parse a single `bytes=` range. Multiple or malformed ranges are ignored
+/// and the whole resource is sent, as RFC 9110 allows.
+fn parse_range(range: &str, len: u64) -> Selection {
+    let Some(spec) = range.trim().strip_prefix("bytes=") else {
+        return Selection::Full;
+    };
+    if spec.contains(',') {
+        return Selection::Full;
+    }
+    let Some((start, end)) = spec.trim().split_once('-') else {
+        return Selection::Full;
+    };
+
+    match (start.trim(), end.trim()) {
+        ("", suffix) => match suffix.parse::<u64>() {
+            Ok(0) => Selection::Unsatisfiable,
+            Ok(_) if len == 0 => Selection::Unsatisfiable,
+            Ok(n) => Selection::Partial(len.saturating_sub(n), len - 1),
+            Err(_) => Selection::Full,
+        },
+        (start, end) => {
+            let Ok(start) = start.parse::<u64>() else {
+                return Selection::Full;
+            };
+            let end = match end {
+                "" => u64::MAX,
+                end => match end.parse::<u64>() {
+                    Ok(end) if end >= start => end,
+                    _ => return Selection::Full,
+                },
+            };
+            if start >= len {
+                Selection::Unsatisfiable
+            } else {
+                Selection::Partial(start, end.min(len - 1))
+            }
+        }
+    }
+}
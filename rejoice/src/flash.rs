@@ -0,0 +1,223 @@
+//! Flash messages: one-off notices that survive a redirect.
+//!
+//! Set a message with `res.flash` before redirecting, and read it on the next
+//! page with `req.flashes()`. Messages are kept in a cookie until a handler
+//! or layout reads them, then cleared.
+//!
+//! ```ignore
+//! // src/routes/settings.rs
+//! pub async fn post(req: Req, res: Res) -> Res {
+//!     save_settings(&req).await;
+//!     res.flash("success", "Settings saved").redirect_see_other("/settings")
+//! }
+//!
+//! // src/routes/layout.rs
+//! pub async fn layout(req: Req, res: Res, children: Children) -> Res {
+//!     res.html(html! {
+//!         @for flash in req.flashes() {
+//!             div class={ "flash flash-" (flash.level) } { (flash.message) }
+//!         }
+//!         (children)
+//!     })
+//! }
+//! ```
+
+use crate::cookies::SecretKeys;
+use crate::request::Cookies;
+use axum::{
+    body::Body,
+    http::{HeaderValue, Request, Response, header},
+};
+use cookie::{Cookie, SameSite};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::task::{Context, Poll};
+use tower::{Layer, Service};
+
+/// Name of the cookie holding pending flash messages.
+pub(crate) const COOKIE_NAME: &str = "rejoice_flash";
+
+/// A flash message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Flash {
+    /// Kind of message, e.g. `"success"` or `"error"`, for styling
+    pub level: String,
+    /// The message text
+    pub message: String,
+}
+
+/// Flash messages sent with a request, shared between the middleware and
+/// every `Req` for that request.
+#[derive(Debug)]
+pub(crate) struct Incoming {
+    messages: Vec<Flash>,
+    read: AtomicBool,
+}
+
+impl Incoming {
+    /// Return the messages and mark them as shown.
+    pub fn take(&self) -> Vec<Flash> {
+        self.read.store(true, Ordering::Relaxed);
+        self.messages.clone()
+    }
+}
+
+/// Encode messages as a JSON list of `[level, message]` pairs.
+fn encode(messages: &[Flash]) -> String {
+    let pairs: Vec<(&str, &str)> = messages
+        .iter()
+        .map(|flash| (flash.level.as_str(), flash.message.as_str()))
+        .collect();
+    serde_json::to_string(&pairs).unwrap_or_default()
+}
+
+/// Parse a flash cookie, ignoring it if it's malformed.
+///
+/// With a secret key, the cookie is signed and ignored if it's been tampered
+/// with. Without one, clients can forge messages, so never flash anything
+/// that must be trusted.
+pub(crate) fn decode(raw: &str, keys: Option<&SecretKeys>) -> Vec<Flash> {
+    let value = match keys {
+        Some(keys) => keys.verify(COOKIE_NAME, raw).unwrap_or_default(),
+        None => raw.to_string(),
+    };
+    serde_json::from_str::<Vec<(String, String)>>(&value)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(level, message)| Flash { level, message })
+        .collect()
+}
+
+/// The cookie that carries `messages` to the next request, signed if the
+/// app has a secret key.
+pub(crate) fn cookie(messages: &[Flash], keys: Option<&SecretKeys>) -> Cookie<'static> {
+    let value = encode(messages);
+    let value = match keys {
+        Some(keys) => keys.sign(COOKIE_NAME, &value),
+        None => value,
+    };
+    Cookie::build((COOKIE_NAME, value))
+        .path("/")
+        .http_only(true)
+        .same_site(SameSite::Lax)
+        .build()
+}
+
+/// Whether the response already sets the flash cookie.
+fn sets_flash_cookie(response: &Response<Body>) -> bool {
+    response
+        .headers()
+        .get_all(header::SET_COOKIE)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .any(|v| v.starts_with(&format!("{}=", COOKIE_NAME)))
+}
+
+#[derive(Clone)]
+pub(crate) struct FlashLayer;
+
+impl<S> Layer<S> for FlashLayer {
+    type Service = FlashMiddleware<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        FlashMiddleware { inner }
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct FlashMiddleware<S> {
+    inner: S,
+}
+
+impl<S> Service<Request<Body>> for FlashMiddleware<S>
+where
+    S: Service<Request<Body>, Response = Response<Body>> + Clone + Send + 'static,
+    S::Future: Send,
+{
+    type Response = Response<Body>;
+    type Error = S::Error;
+    type Future = std::pin::Pin<
+        Box<dyn std::future::Future<Output = Result<Self::Response, Self::Error>> + Send>,
+    >;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request<Body>) -> Self::Future {
+        let mut inner = self.inner.clone();
+
+        let keys = req.extensions().get::<Arc<SecretKeys>>().cloned();
+        let messages = Cookies::from_header(
+            req.headers()
+                .get(header::COOKIE)
+                .and_then(|v| v.to_str().ok()),
+        )
+        .get(COOKIE_NAME)
+        .map(|raw| decode(raw, keys.as_deref()))
+        .unwrap_or_default();
+
+        // Requests without flashes skip the bookkeeping
+        if messages.is_empty() {
+            return Box::pin(async move { inner.call(req).await });
+        }
+
+        let incoming = Arc::new(Incoming {
+            messages,
+            read: AtomicBool::new(false),
+        });
+        req.extensions_mut().insert(incoming.clone());
+
+        Box::pin(async move {
+            let mut response = inner.call(req).await?;
+
+            // Messages are cleared once shown; until then they survive any
+            // number of redirects. New messages replace the cookie anyway.
+            if incoming.read.load(Ordering::Relaxed) && !sets_flash_cookie(&response) {
+                let mut removal = cookie(&[], None);
+                removal.make_removal();
+                if let Ok(value) = HeaderValue::from_str(&removal.encoded().to_string()) {
+                    response.headers_mut().append(header::SET_COOKIE, value);
+                }
+            }
+
+            Ok(response)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn messages() -> Vec<Flash> {
+        vec![Flash {
+            level: "success".to_string(),
+            message: "Saved".to_string(),
+        }]
+    }
+
+    #[test]
+    fn signed_cookie_round_trips() {
+        let keys = SecretKeys::new(b"0123456789abcdef0123456789abcdef");
+        let cookie = cookie(&messages(), Some(&keys));
+        assert_eq!(decode(cookie.value(), Some(&keys)), messages());
+    }
+
+    #[test]
+    fn forged_cookie_is_ignored_with_a_secret_key() {
+        let keys = SecretKeys::new(b"0123456789abcdef0123456789abcdef");
+        let forged = encode(&messages());
+        assert!(decode(&forged, Some(&keys)).is_empty());
+
+        let signed = cookie(&messages(), Some(&keys));
+        let tampered = signed.value().replace("Saved", "Deleted");
+        assert!(decode(&tampered, Some(&keys)).is_empty());
+    }
+
+    #[test]
+    fn unsigned_cookie_is_read_without_a_secret_key() {
+        let cookie = cookie(&messages(), None);
+        assert_eq!(decode(cookie.value(), None), messages());
+    }
+}
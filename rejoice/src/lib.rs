@@ -1,11 +1,30 @@
 mod app;
 pub mod codegen;
+mod cookies;
+pub mod cors;
+pub mod csrf;
 #[cfg(feature = "sqlite")]
 pub mod db;
 pub mod env;
+mod error;
+mod etag;
+mod files;
+pub mod flash;
 mod island;
+mod listen;
+pub mod method_override;
+mod negotiate;
+mod partial;
+mod proxy;
+mod redirect;
 mod request;
 mod response;
+pub mod session;
+pub mod sse;
+mod stream;
+#[cfg(feature = "tls")]
+mod tls;
+pub mod websocket;
 
 // Re-export dotenvy for the env! macro
 #[doc(hidden)]
@@ -13,8 +32,23 @@ pub use dotenvy_macro;
 
 // Core types
 pub use app::App;
+pub use error::{Error, ErrorPages, IntoErrorResponse};
+pub use flash::Flash;
+pub use redirect::is_safe_redirect;
 pub use request::{Body, BodyParseError, Req};
 pub use response::Res;
+pub use session::Session;
+pub use stream::IntoChunk;
+pub use websocket::WebSocket;
+
+// CSRF protection
+pub use csrf::{csrf_field, csrf_meta};
+
+// Method override for HTML forms
+pub use method_override::method_field;
+
+// Cookies
+pub use cookie::{Cookie, CookieBuilder, Expiration, SameSite, time};
 
 // Axum extractors that are still useful
 pub use axum::extract::Path;
@@ -24,6 +58,10 @@ pub use axum::extract::Path;
 pub use axum::extract::State;
 #[doc(hidden)]
 pub use axum::{Router, routing};
+#[doc(hidden)]
+pub use axum::response::{IntoResponse, Response};
+#[doc(hidden)]
+pub use error::IntoRes;
 
 // Island support
 pub use island::island_fn;
@@ -79,7 +117,8 @@ macro_rules! routes {
 /// ```
 pub mod prelude {
     pub use crate::{
-        html, island, json, App, Children, Markup, Path, PreEscaped, Req, Res, DOCTYPE,
+        csrf_field, html, island, json, method_field, App, Children, Cookie, Error, Markup,
+        Path, PreEscaped, Req, Res, DOCTYPE,
     };
 }
 
@@ -0,0 +1,98 @@
+//! Sockets the server listens on: TCP, Unix domain sockets, and sockets
+//! inherited through systemd socket activation.
+
+use std::io;
+use std::net::SocketAddr;
+use tokio::net::TcpListener;
+
+#[cfg(unix)]
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+#[cfg(unix)]
+use std::path::{Path, PathBuf};
+#[cfg(unix)]
+use tokio::net::UnixListener;
+
+/// A listening socket, ready to serve.
+pub(crate) enum Bound {
+    Tcp(TcpListener),
+    /// A Unix socket, with the path to remove on shutdown if the app
+    /// created it
+    #[cfg(unix)]
+    Unix(UnixListener, Option<PathBuf>),
+}
+
+/// Bind a TCP listener, naming the address in the error.
+pub(crate) async fn bind_tcp(addr: SocketAddr) -> io::Result<TcpListener> {
+    TcpListener::bind(addr).await.map_err(|error| {
+        io::Error::new(
+            error.kind(),
+            format!("could not listen on {}: {}", addr, error),
+        )
+    })
+}
+
+/// Bind a Unix socket at `path`, replacing a stale socket file left by a
+/// previous run.
+#[cfg(unix)]
+pub(crate) fn bind_unix(path: &Path, mode: Option<u32>) -> io::Result<UnixListener> {
+    let context = |error: io::Error| {
+        io::Error::new(
+            error.kind(),
+            format!("could not listen on {}: {}", path.display(), error),
+        )
+    };
+
+    if let Ok(metadata) = std::fs::symlink_metadata(path) {
+        if !metadata.file_type().is_socket() {
+            return Err(context(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                "the file exists and is not a socket",
+            )));
+        }
+        if std::os::unix::net::UnixStream::connect(path).is_ok() {
+            return Err(context(io::Error::new(
+                io::ErrorKind::AddrInUse,
+                "another process is listening on it",
+            )));
+        }
+        std::fs::remove_file(path).map_err(context)?;
+    }
+
+    let listener = UnixListener::bind(path).map_err(context)?;
+    if let Some(mode) = mode {
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode)).map_err(context)?;
+    }
+    Ok(listener)
+}
+
+/// The first socket passed by systemd socket activation (`LISTEN_FDS`), if
+/// any.
+#[cfg(unix)]
+pub(crate) fn inherited() -> io::Result<Option<Bound>> {
+    let mut fds = listenfd::ListenFd::from_env();
+    if fds.len() == 0 {
+        return Ok(None);
+    }
+
+    let context = |error: io::Error| {
+        io::Error::new(
+            error.kind(),
+            format!("could not use the socket passed by systemd: {}", error),
+        )
+    };
+
+    // Taking a socket of the wrong type leaves it in place, so try both
+    if let Ok(Some(listener)) = fds.take_tcp_listener(0) {
+        listener.set_nonblocking(true).map_err(context)?;
+        let listener = TcpListener::from_std(listener).map_err(context)?;
+        return Ok(Some(Bound::Tcp(listener)));
+    }
+    match fds.take_unix_listener(0).map_err(context)? {
+        Some(listener) => {
+            listener.set_nonblocking(true).map_err(context)?;
+            let listener = UnixListener::from_std(listener).map_err(context)?;
+            Ok(Some(Bound::Unix(listener, None)))
+        }
+        None => Ok(None),
+    }
+}
@@ -0,0 +1,128 @@
+//! HTML form method override.
+//!
+//! HTML forms can only submit GET and POST. A POST carrying a `_method` form
+//! field (rendered with [`method_field`]) or an `X-HTTP-Method-Override`
+//! header is routed as PUT, PATCH or DELETE instead, so route files can use
+//! their `put`, `patch` and `delete` handlers from plain forms.
+
+use axum::{
+    body::Body,
+    http::{Method, Request, header},
+};
+use maud::{Markup, html};
+use std::collections::HashMap;
+use std::task::{Context, Poll};
+use tower::{Layer, Service};
+
+/// Form field carrying the override method.
+pub const FIELD_NAME: &str = "_method";
+
+/// Header carrying the override method.
+pub const HEADER_NAME: &str = "x-http-method-override";
+
+/// Render a hidden input that makes a POST form submit as `method`.
+///
+/// # Example
+/// ```ignore
+/// html! {
+///     form method="post" action={ "/posts/" (post.id) } {
+///         (method_field("DELETE"))
+///         button { "Delete" }
+///     }
+/// }
+/// ```
+pub fn method_field(method: &str) -> Markup {
+    html! {
+        input type="hidden" name=(FIELD_NAME) value=(method.to_ascii_uppercase());
+    }
+}
+
+/// Parse an override value, allowing only methods forms can't send.
+fn parse_override(value: &str) -> Option<Method> {
+    match value.trim().to_ascii_uppercase().as_str() {
+        "PUT" => Some(Method::PUT),
+        "PATCH" => Some(Method::PATCH),
+        "DELETE" => Some(Method::DELETE),
+        _ => None,
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct MethodOverrideLayer;
+
+impl<S> Layer<S> for MethodOverrideLayer {
+    type Service = MethodOverrideMiddleware<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        MethodOverrideMiddleware { inner }
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct MethodOverrideMiddleware<S> {
+    inner: S,
+}
+
+impl<S> Service<Request<Body>> for MethodOverrideMiddleware<S>
+where
+    S: Service<Request<Body>> + Clone + Send + 'static,
+    S::Future: Send,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = std::pin::Pin<
+        Box<dyn std::future::Future<Output = Result<Self::Response, Self::Error>> + Send>,
+    >;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        let mut inner = self.inner.clone();
+
+        Box::pin(async move {
+            if req.method() != Method::POST {
+                return inner.call(req).await;
+            }
+
+            // The header takes precedence over the form field
+            if let Some(method) = req
+                .headers()
+                .get(HEADER_NAME)
+                .and_then(|v| v.to_str().ok())
+                .and_then(parse_override)
+            {
+                let mut req = req;
+                *req.method_mut() = method;
+                return inner.call(req).await;
+            }
+
+            let is_form = req
+                .headers()
+                .get(header::CONTENT_TYPE)
+                .and_then(|v| v.to_str().ok())
+                .is_some_and(|v| v.starts_with("application/x-www-form-urlencoded"));
+            if !is_form {
+                return inner.call(req).await;
+            }
+
+            // Reading the form consumes the body, so rebuild the request with
+            // the buffered bytes for the handler
+            let (mut parts, body) = req.into_parts();
+            let bytes = axum::body::to_bytes(body, usize::MAX)
+                .await
+                .unwrap_or_default();
+            if let Some(method) = serde_urlencoded::from_bytes::<HashMap<String, String>>(&bytes)
+                .ok()
+                .and_then(|fields| fields.get(FIELD_NAME).and_then(|m| parse_override(m)))
+            {
+                parts.method = method;
+            }
+
+            inner
+                .call(Request::from_parts(parts, Body::from(bytes)))
+                .await
+        })
+    }
+}
@@ -0,0 +1,138 @@
+//! Content negotiation for `Accept`-style headers.
+
+use axum::http::{HeaderMap, HeaderName};
+
+/// One entry of an `Accept`-style header, e.g. `text/html;q=0.8`.
+struct Preference {
+    value: String,
+    quality: f32,
+}
+
+/// Parse a comma-separated header with optional `q` parameters.
+///
+/// Entries with an invalid quality are skipped.
+fn parse(headers: &HeaderMap, name: HeaderName) -> Option<Vec<Preference>> {
+    let values: Vec<&str> = headers
+        .get_all(name)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .collect();
+
+    if values.is_empty() {
+        return None;
+    }
+
+    let prefs = values
+        .iter()
+        .flat_map(|v| v.split(','))
+        .filter_map(|entry| {
+            let mut params = entry.split(';');
+            let value = params.next()?.trim().to_ascii_lowercase();
+            if value.is_empty() {
+                return None;
+            }
+
+            let mut quality = 1.0;
+            for param in params {
+                if let Some((key, q)) = param.trim().split_once('=')
+                    && key.trim().eq_ignore_ascii_case("q")
+                {
+                    quality = q.trim().parse::<f32>().ok().filter(|q| (0.0..=1.0).contains(q))?;
+                }
+            }
+
+            Some(Preference { value, quality })
+        })
+        .collect();
+
+    Some(prefs)
+}
+
+/// Pick the best offer given the header's preferences.
+///
+/// `specificity` returns how precisely a preference matches an offer (higher
+/// is more specific), or `None` if it doesn't match. The most specific
+/// matching preference decides an offer's quality, as described in RFC 9110,
+/// and offers the header doesn't mention get `default_quality`. Without the
+/// header at all, the first offer wins.
+fn best<'a>(
+    headers: &HeaderMap,
+    name: HeaderName,
+    offers: &[&'a str],
+    default_quality: impl Fn(&str) -> f32,
+    specificity: impl Fn(&str, &str) -> Option<usize>,
+) -> Option<&'a str> {
+    let Some(prefs) = parse(headers, name) else {
+        return offers.first().copied();
+    };
+
+    let mut best: Option<(&'a str, f32)> = None;
+    for offer in offers {
+        let lower = offer.to_ascii_lowercase();
+        let quality = prefs
+            .iter()
+            .filter_map(|p| specificity(&p.value, &lower).map(|s| (s, p.quality)))
+            .max_by_key(|(s, _)| *s)
+            .map(|(_, q)| q)
+            .unwrap_or_else(|| default_quality(&lower));
+
+        if quality > 0.0 && best.is_none_or(|(_, q)| quality > q) {
+            best = Some((offer, quality));
+        }
+    }
+
+    best.map(|(offer, _)| offer)
+}
+
+/// Pick the best media type from `offers` according to `Accept`.
+pub(crate) fn media_type<'a>(headers: &HeaderMap, offers: &[&'a str]) -> Option<&'a str> {
+    best(headers, axum::http::header::ACCEPT, offers, |_| 0.0, |range, offer| {
+        let offer = offer.split(';').next().unwrap_or(offer).trim();
+        let (range_type, range_sub) = range.split_once('/')?;
+        let (offer_type, offer_sub) = offer.split_once('/')?;
+
+        if range_type == "*" && range_sub == "*" {
+            Some(0)
+        } else if range_type == offer_type && range_sub == "*" {
+            Some(1)
+        } else if range_type == offer_type && range_sub == offer_sub {
+            Some(2)
+        } else {
+            None
+        }
+    })
+}
+
+/// Pick the best language tag from `offers` according to `Accept-Language`.
+///
+/// A range like `en` matches `en` and `en-US`.
+pub(crate) fn language<'a>(headers: &HeaderMap, offers: &[&'a str]) -> Option<&'a str> {
+    best(headers, axum::http::header::ACCEPT_LANGUAGE, offers, |_| 0.0, |range, offer| {
+        if range == "*" {
+            Some(0)
+        } else if offer == range {
+            Some(range.len() + 1)
+        } else if offer.starts_with(range) && offer[range.len()..].starts_with('-') {
+            Some(range.len())
+        } else {
+            None
+        }
+    })
+}
+
+/// Pick the best content coding from `offers` according to `Accept-Encoding`.
+///
+/// `identity` stays acceptable unless the header explicitly rules it out.
+pub(crate) fn encoding<'a>(headers: &HeaderMap, offers: &[&'a str]) -> Option<&'a str> {
+    best(
+        headers,
+        axum::http::header::ACCEPT_ENCODING,
+        offers,
+        |offer| if offer == "identity" { 0.001 } else { 0.0 },
+        |range, offer| match range {
+            "*" => Some(0),
+            r if r == offer => Some(1),
+            _ => None,
+        },
+    )
+}
@@ -0,0 +1,37 @@
+//! Partial rendering for htmx-style requests, which swap a page's content
+//! into the current document instead of loading a whole new one.
+
+use axum::http::{HeaderMap, HeaderName, Uri};
+
+/// The request header marking partial requests, set with
+/// `App::partial_header`.
+#[derive(Debug, Clone)]
+pub(crate) struct PartialHeader(pub HeaderName);
+
+impl Default for PartialHeader {
+    fn default() -> Self {
+        Self(HeaderName::from_static("hx-request"))
+    }
+}
+
+/// Response extension marking a partial page or fragment, which gets no
+/// injected scripts or styles.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Partial;
+
+/// Whether a request only wants the page's own content, without layouts.
+pub(crate) fn is_partial(headers: &HeaderMap, header: &HeaderName) -> bool {
+    headers.contains_key(header)
+        // Boosted links and history restores replace the whole page
+        && !headers.contains_key("hx-boosted")
+        && !headers.contains_key("hx-history-restore-request")
+}
+
+/// The fragment named by the `fragment` query parameter, if any.
+pub(crate) fn fragment(uri: &Uri) -> Option<String> {
+    serde_urlencoded::from_str::<Vec<(String, String)>>(uri.query()?)
+        .ok()?
+        .into_iter()
+        .find(|(key, _)| key == "fragment")
+        .map(|(_, name)| name)
+}
@@ -0,0 +1,218 @@
+use axum::http::{HeaderMap, header};
+use std::net::IpAddr;
+
+/// Set of proxy addresses whose forwarding headers are trusted.
+///
+/// Built from entries like `"127.0.0.1"`, `"::1"` or `"10.0.0.0/8"`, and
+/// `"unix"` for peers on a Unix socket.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct TrustedProxies {
+    ranges: Vec<(IpAddr, u8)>,
+    unix: bool,
+}
+
+/// Client information resolved from the socket peer and forwarding headers.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct ClientInfo {
+    pub ip: Option<IpAddr>,
+    pub secure: bool,
+}
+
+/// A single hop parsed from `Forwarded` or `X-Forwarded-*` headers.
+struct Hop {
+    addr: Option<IpAddr>,
+    proto: Option<String>,
+}
+
+impl TrustedProxies {
+    /// Parse a list of IP addresses and CIDR ranges.
+    pub fn parse<I, S>(entries: I) -> Result<Self, String>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let mut ranges = Vec::new();
+        let mut unix = false;
+
+        for entry in entries {
+            let entry = entry.as_ref().trim();
+            if entry.eq_ignore_ascii_case("unix") {
+                unix = true;
+                continue;
+            }
+
+            let (addr, prefix) = match entry.split_once('/') {
+                Some((addr, prefix)) => (addr, Some(prefix)),
+                None => (entry, None),
+            };
+
+            let addr: IpAddr = addr
+                .parse()
+                .map_err(|_| format!("Invalid trusted proxy address: {}", entry))?;
+            let max_prefix = if addr.is_ipv4() { 32 } else { 128 };
+            let prefix = match prefix {
+                Some(p) => p
+                    .parse::<u8>()
+                    .ok()
+                    .filter(|p| *p <= max_prefix)
+                    .ok_or_else(|| format!("Invalid trusted proxy prefix: {}", entry))?,
+                None => max_prefix,
+            };
+
+            ranges.push((addr, prefix));
+        }
+
+        Ok(Self { ranges, unix })
+    }
+
+    /// Check whether an address belongs to one of the trusted ranges.
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        let ip = canonical(ip);
+        self.ranges.iter().any(|(net, prefix)| match (canonical(*net), ip) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - *prefix as u32).unwrap_or(0);
+                u32::from(net) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - *prefix as u32).unwrap_or(0);
+                u128::from(net) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        })
+    }
+
+    /// Resolve the originating client for a request received from `peer`,
+    /// which is `None` for connections without an IP address.
+    ///
+    /// Forwarding headers are only honored when the peer is trusted. The
+    /// chain is walked right to left, skipping trusted proxies, and the first
+    /// untrusted hop is taken as the client.
+    pub fn resolve(&self, peer: Option<IpAddr>, unix_peer: bool, headers: &HeaderMap) -> ClientInfo {
+        let trusted = match peer {
+            Some(peer) => self.contains(peer),
+            None => unix_peer && self.unix,
+        };
+
+        let mut client = ClientInfo {
+            ip: peer,
+            secure: false,
+        };
+        if !trusted {
+            return client;
+        }
+
+        let hops = forwarded_hops(headers).unwrap_or_else(|| x_forwarded_hops(headers));
+
+        for hop in hops.iter().rev() {
+            let Some(addr) = hop.addr else {
+                break;
+            };
+            client.ip = Some(addr);
+            if let Some(proto) = &hop.proto {
+                client.secure = proto.eq_ignore_ascii_case("https");
+            }
+            if !self.contains(addr) {
+                break;
+            }
+        }
+
+        client
+    }
+}
+
+/// Treat IPv4-mapped IPv6 addresses as plain IPv4.
+fn canonical(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(v6) => v6
+            .to_ipv4_mapped()
+            .map(IpAddr::V4)
+            .unwrap_or(IpAddr::V6(v6)),
+        v4 => v4,
+    }
+}
+
+/// Parse the standard `Forwarded` header (RFC 7239).
+fn forwarded_hops(headers: &HeaderMap) -> Option<Vec<Hop>> {
+    let values: Vec<&str> = headers
+        .get_all(header::FORWARDED)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .collect();
+
+    if values.is_empty() {
+        return None;
+    }
+
+    let hops = values
+        .iter()
+        .flat_map(|v| v.split(','))
+        .map(|element| {
+            let mut hop = Hop {
+                addr: None,
+                proto: None,
+            };
+            for pair in element.split(';') {
+                let Some((key, value)) = pair.trim().split_once('=') else {
+                    continue;
+                };
+                let value = value.trim().trim_matches('"');
+                if key.eq_ignore_ascii_case("for") {
+                    hop.addr = parse_node(value);
+                } else if key.eq_ignore_ascii_case("proto") {
+                    hop.proto = Some(value.to_string());
+                }
+            }
+            hop
+        })
+        .collect();
+
+    Some(hops)
+}
+
+/// Parse the de-facto `X-Forwarded-For` and `X-Forwarded-Proto` headers.
+fn x_forwarded_hops(headers: &HeaderMap) -> Vec<Hop> {
+    let list = |name: &str| -> Vec<String> {
+        headers
+            .get_all(name)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(','))
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty())
+            .collect()
+    };
+
+    let addrs = list("x-forwarded-for");
+    let protos = list("x-forwarded-proto");
+
+    // When each proxy appended its own proto the lists line up; otherwise the
+    // nearest proxy's value describes the original request.
+    let aligned = protos.len() == addrs.len();
+
+    addrs
+        .iter()
+        .enumerate()
+        .map(|(i, addr)| Hop {
+            addr: parse_node(addr),
+            proto: if aligned {
+                protos.get(i).cloned()
+            } else if i == addrs.len() - 1 {
+                protos.last().cloned()
+            } else {
+                None
+            },
+        })
+        .collect()
+}
+
+/// Parse a node identifier, which may carry a port or IPv6 brackets.
+fn parse_node(value: &str) -> Option<IpAddr> {
+    if let Some(rest) = value.strip_prefix('[') {
+        return rest.split_once(']')?.0.parse().ok();
+    }
+    if let Ok(ip) = value.parse() {
+        return Some(ip);
+    }
+    let (host, _port) = value.rsplit_once(':')?;
+    host.parse().ok()
+}
@@ -1,10 +1,23 @@
+use crate::cookies::SecretKeys;
+use crate::flash::{self, Flash};
+use crate::negotiate;
+use crate::partial::{self, PartialHeader};
+use crate::proxy::TrustedProxies;
+use crate::session::Session;
 use axum::{
     body::Bytes,
-    extract::FromRequest,
-    http::{HeaderMap, Method, Request, Uri},
+    extract::{ConnectInfo, FromRequest},
+    http::{HeaderMap, Method, Request, Uri, uri::Scheme},
 };
+use cookie::Cookie;
 use serde::de::DeserializeOwned;
 use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+
+/// Marks requests that arrived over a TLS connection served by the app.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct SecureConnection;
 
 /// Incoming request data.
 ///
@@ -21,6 +34,149 @@ pub struct Req {
     pub uri: Uri,
     /// Request body (for POST, PUT, etc.)
     pub body: Body,
+    remote_addr: Option<SocketAddr>,
+    client_ip: Option<IpAddr>,
+    secure: bool,
+    session: Option<Session>,
+    flashes: Option<Arc<flash::Incoming>>,
+    pub(crate) partial_header: Arc<PartialHeader>,
+}
+
+impl Req {
+    /// The address of the directly connected peer.
+    ///
+    /// Behind a reverse proxy this is the proxy's address; use
+    /// [`client_ip`](Self::client_ip) for the originating client.
+    pub fn remote_addr(&self) -> Option<SocketAddr> {
+        self.remote_addr
+    }
+
+    /// The IP address of the originating client.
+    ///
+    /// `X-Forwarded-For` and `Forwarded` are only honored when the request
+    /// comes from a proxy configured with `App::trusted_proxies`.
+    ///
+    /// # Example
+    /// ```ignore
+    /// pub async fn get(req: Req, res: Res) -> Res {
+    ///     let ip = req.client_ip().map(|ip| ip.to_string()).unwrap_or_default();
+    ///     res.html(html! { p { "Your IP: " (ip) } })
+    /// }
+    /// ```
+    pub fn client_ip(&self) -> Option<IpAddr> {
+        self.client_ip
+    }
+
+    /// Check if the client connected over HTTPS.
+    ///
+    /// True for connections served with `App::tls`. Honors
+    /// `X-Forwarded-Proto` and `Forwarded` from trusted proxies.
+    pub fn is_secure(&self) -> bool {
+        self.secure
+    }
+
+    /// The session for this request.
+    ///
+    /// Changes are saved after the handler returns. See `rejoice::session`.
+    ///
+    /// # Example
+    /// ```ignore
+    /// pub async fn get(req: Req, res: Res) -> Res {
+    ///     let Some(user_id) = req.session().get::<i64>("user_id") else {
+    ///         return res.redirect("/login");
+    ///     };
+    ///     // ...
+    /// }
+    /// ```
+    ///
+    /// # Panics
+    /// Panics if sessions aren't enabled with `App::sessions`.
+    pub fn session(&self) -> Session {
+        self.session
+            .clone()
+            .expect("Sessions are not enabled; call App::sessions")
+    }
+
+    /// Flash messages set with `res.flash` on an earlier response.
+    ///
+    /// Once read, they're cleared when this response is sent, so each
+    /// message is shown once. Pages and layouts handling the same request
+    /// all see the same messages.
+    ///
+    /// # Example
+    /// ```ignore
+    /// html! {
+    ///     @for flash in req.flashes() {
+    ///         div class={ "flash flash-" (flash.level) } { (flash.message) }
+    ///     }
+    /// }
+    /// ```
+    pub fn flashes(&self) -> Vec<Flash> {
+        self.flashes
+            .as_ref()
+            .map(|incoming| incoming.take())
+            .unwrap_or_default()
+    }
+
+    /// Whether the request asks for the page without its layouts, as htmx
+    /// does with `HX-Request` when swapping content into the current page.
+    ///
+    /// Boosted links and history restores still get the full page. The
+    /// header can be changed with `App::partial_header`.
+    pub fn is_partial(&self) -> bool {
+        partial::is_partial(&self.headers, &self.partial_header.0)
+    }
+
+    /// The fragment requested with `?fragment=` (used by generated route
+    /// wrappers).
+    #[doc(hidden)]
+    pub fn fragment(&self) -> Option<String> {
+        partial::fragment(&self.uri)
+    }
+
+    /// The CSRF token for this request's session.
+    ///
+    /// Templates usually render it with `csrf_field()` instead.
+    ///
+    /// # Panics
+    /// Panics if sessions aren't enabled with `App::sessions`.
+    pub fn csrf_token(&self) -> String {
+        crate::csrf::token_for(&self.session())
+    }
+
+    /// Pick the best media type from `offers` according to the `Accept` header.
+    ///
+    /// Quality values and wildcards like `text/*` are honored. Returns `None`
+    /// if the client accepts none of the offers, or the first offer if the
+    /// request has no `Accept` header.
+    ///
+    /// # Example
+    /// ```ignore
+    /// match req.accepts(&["text/html", "application/json"]) {
+    ///     Some("application/json") => res.json(&user),
+    ///     _ => res.html(html! { h1 { (user.name) } }),
+    /// }
+    /// ```
+    pub fn accepts<'a>(&self, offers: &[&'a str]) -> Option<&'a str> {
+        negotiate::media_type(&self.headers, offers)
+    }
+
+    /// Check if the client prefers JSON over HTML.
+    pub fn prefers_json(&self) -> bool {
+        self.accepts(&["text/html", "application/json"]) == Some("application/json")
+    }
+
+    /// Pick the best language tag from `offers` according to `Accept-Language`.
+    ///
+    /// A range like `en` matches offers such as `en-US`.
+    pub fn accepts_language<'a>(&self, offers: &[&'a str]) -> Option<&'a str> {
+        negotiate::language(&self.headers, offers)
+    }
+
+    /// Pick the best content coding from `offers` according to `Accept-Encoding`.
+    pub fn accepts_encoding<'a>(&self, offers: &[&'a str]) -> Option<&'a str> {
+        negotiate::encoding(&self.headers, offers)
+    }
 }
 
 /// Request body with parsing methods.
@@ -118,23 +274,35 @@ impl Body {
 #[derive(Debug, Clone, Default)]
 pub struct Cookies {
     cookies: HashMap<String, String>,
+    keys: Option<Arc<SecretKeys>>,
 }
 
 impl Cookies {
-    /// Parse cookies from a Cookie header value
+    /// Parse cookies from a Cookie header value.
+    ///
+    /// Values are percent-decoded and surrounding quotes are removed.
+    /// Malformed entries are skipped, and if a name appears more than once
+    /// the first (most specific) value wins.
     pub fn from_header(header: Option<&str>) -> Self {
         let mut cookies = HashMap::new();
 
         if let Some(header) = header {
-            for part in header.split(';') {
-                let part = part.trim();
-                if let Some((name, value)) = part.split_once('=') {
-                    cookies.insert(name.trim().to_string(), value.trim().to_string());
-                }
+            for cookie in Cookie::split_parse_encoded(header).flatten() {
+                cookies
+                    .entry(cookie.name().to_string())
+                    .or_insert_with(|| cookie.value_trimmed().to_string());
             }
         }
 
-        Self { cookies }
+        Self {
+            cookies,
+            keys: None,
+        }
+    }
+
+    pub(crate) fn with_keys(mut self, keys: Option<Arc<SecretKeys>>) -> Self {
+        self.keys = keys;
+        self
     }
 
     /// Get a cookie value by name
@@ -142,6 +310,29 @@ impl Cookies {
         self.cookies.get(name).map(|s| s.as_str())
     }
 
+    /// Get the value of a cookie set with `Res::set_signed_cookie`.
+    ///
+    /// Returns `None` if the cookie is missing, was tampered with, or no
+    /// secret key is configured with `App::secret_key`.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let Some(user_id) = req.cookies.get_signed("user_id") else {
+    ///     return res.redirect("/login");
+    /// };
+    /// ```
+    pub fn get_signed(&self, name: &str) -> Option<String> {
+        self.keys.as_ref()?.verify(name, self.get(name)?)
+    }
+
+    /// Get the decrypted value of a cookie set with `Res::set_private_cookie`.
+    ///
+    /// Returns `None` if the cookie is missing, was tampered with, or no
+    /// secret key is configured with `App::secret_key`.
+    pub fn get_private(&self, name: &str) -> Option<String> {
+        self.keys.as_ref()?.decrypt(name, self.get(name)?)
+    }
+
     /// Check if a cookie exists
     pub fn has(&self, name: &str) -> bool {
         self.cookies.contains_key(name)
@@ -167,10 +358,32 @@ where
             headers
                 .get(axum::http::header::COOKIE)
                 .and_then(|v| v.to_str().ok()),
-        );
+        )
+        .with_keys(parts.extensions.get::<Arc<SecretKeys>>().cloned());
         let method = parts.method;
         let uri = parts.uri;
-        
+
+        let remote_addr = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| *addr);
+        #[cfg(unix)]
+        let unix_peer = parts
+            .extensions
+            .get::<ConnectInfo<tokio::net::unix::SocketAddr>>()
+            .is_some();
+        #[cfg(not(unix))]
+        let unix_peer = false;
+        let client = parts
+            .extensions
+            .get::<Arc<TrustedProxies>>()
+            .map(|proxies| proxies.resolve(remote_addr.map(|a| a.ip()), unix_peer, &headers))
+            .unwrap_or_default();
+        let client_ip = client.ip.or(remote_addr.map(|a| a.ip()));
+        let secure = client.secure
+            || uri.scheme() == Some(&Scheme::HTTPS)
+            || parts.extensions.get::<SecureConnection>().is_some();
+
         // Read the body bytes
         let bytes = axum::body::to_bytes(body, usize::MAX)
             .await
@@ -182,6 +395,16 @@ where
             method,
             uri,
             body: Body::new(bytes),
+            remote_addr,
+            client_ip,
+            secure,
+            session: parts.extensions.get::<Session>().cloned(),
+            flashes: parts.extensions.get::<Arc<flash::Incoming>>().cloned(),
+            partial_header: parts
+                .extensions
+                .get::<Arc<PartialHeader>>()
+                .cloned()
+                .unwrap_or_default(),
         })
     }
 }
@@ -1,89 +1,272 @@
 use axum::{
     body::Body,
     extract::FromRequestParts,
-    http::{header, request::Parts, HeaderName, HeaderValue, StatusCode},
+    http::{header, request::Parts, HeaderMap, HeaderName, HeaderValue, Method, StatusCode},
     response::IntoResponse,
 };
-use maud::Markup;
+use crate::Req;
+use crate::cookies::SecretKeys;
+use crate::csrf;
+use crate::error::{self, DefaultErrorPages, ErrorPages};
+use crate::redirect;
+use crate::files::{self, Selection, Validators};
+use crate::flash::{self, Flash};
+use crate::sse::{self, Event};
+use crate::partial::Partial;
+use crate::stream::{IntoChunk, SLOT, StreamBody, Streaming};
+use colored::Colorize;
+use cookie::{Cookie, SameSite};
+use futures::Stream;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio_util::io::ReaderStream;
+use maud::{Markup, PreEscaped};
 use serde::Serialize;
-use std::{cell::RefCell, collections::HashMap};
+use std::sync::{Arc, Mutex, MutexGuard};
 
 /// Response builder with interior mutability.
 ///
 /// Use `set_*` methods to configure headers, cookies, and status,
 /// then finalize with `html()`, `json()`, `redirect()`, or `raw()`.
+///
+/// `Res` is `Send + Sync`, so handlers can hold `&res` across `.await`
+/// points, e.g. set a cookie, query the database, then render.
 #[derive(Debug)]
 pub struct Res {
-    inner: RefCell<ResInner>,
+    inner: Mutex<ResInner>,
+    ctx: Arc<ResContext>,
+}
+
+/// Request data that shapes how a response is built.
+#[derive(Debug, Default)]
+struct ResContext {
+    /// Method of the request being answered
+    method: Method,
+    /// Headers of the request being answered
+    headers: HeaderMap,
+    /// Keys for signed and private cookies, if configured
+    keys: Option<Arc<SecretKeys>>,
+    /// Pages registered with `App::error_pages`
+    error_pages: Option<Arc<dyn ErrorPages>>,
 }
 
 #[derive(Debug, Default)]
 struct ResInner {
     status: Option<StatusCode>,
-    headers: HashMap<String, String>,
-    cookies: Vec<(String, String, CookieOptions)>,
+    headers: HeaderMap,
+    cookies: Vec<Cookie<'static>>,
     body: Option<ResBody>,
-}
-
-#[derive(Debug, Clone)]
-struct CookieOptions {
-    path: Option<String>,
-    max_age: Option<i64>,
-    http_only: bool,
-    secure: bool,
-    same_site: Option<String>,
-}
-
-impl Default for CookieOptions {
-    fn default() -> Self {
-        Self {
-            path: Some("/".to_string()),
-            max_age: None,
-            http_only: true,
-            secure: false,
-            same_site: Some("Lax".to_string()),
-        }
-    }
+    /// A piece of a page for htmx-style swaps, which mustn't get the
+    /// document's scripts and styles
+    partial: bool,
 }
 
 #[derive(Debug, Clone)]
 enum ResBody {
     Html(String),
     Json(String),
-    Redirect(String, bool), // (url, permanent)
+    Redirect(String, StatusCode),
     Raw(Vec<u8>),
+    Stream(StreamBody),
 }
 
 impl Res {
     /// Create a new response builder
     pub fn new() -> Self {
         Self {
-            inner: RefCell::new(ResInner::default()),
+            inner: Mutex::new(ResInner::default()),
+            ctx: Arc::default(),
+        }
+    }
+
+    /// Create a response builder for the request described by `parts`.
+    pub(crate) fn for_request(parts: &Parts) -> Self {
+        Self {
+            inner: Mutex::new(ResInner::default()),
+            ctx: Arc::new(ResContext {
+                method: parts.method.clone(),
+                headers: parts.headers.clone(),
+                keys: parts.extensions.get::<Arc<SecretKeys>>().cloned(),
+                error_pages: parts.extensions.get::<Arc<dyn ErrorPages>>().cloned(),
+            }),
         }
     }
 
-    /// Set a response header
+    fn inner(&self) -> MutexGuard<'_, ResInner> {
+        // A panic while holding the lock can't leave ResInner half-updated in
+        // a way that matters, so recover from poisoning
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Set the body and default the status to 200 OK, then hand back the
+    /// finished response.
+    fn finalize(&self, body: ResBody) -> Self {
+        {
+            let mut inner = self.inner();
+            inner.body = Some(body);
+            inner.status.get_or_insert(StatusCode::OK);
+        }
+        self.clone()
+    }
+
+    /// Create an empty response for the same request (used by layout wrapping)
+    #[doc(hidden)]
+    pub fn fresh(&self) -> Self {
+        Self {
+            inner: Mutex::new(ResInner::default()),
+            ctx: self.ctx.clone(),
+        }
+    }
+
+    /// Set a response header, replacing any earlier values.
+    ///
+    /// Headers with an invalid name or value are dropped, with a warning in
+    /// dev mode.
     pub fn set_header(&self, name: impl Into<String>, value: impl Into<String>) -> &Self {
-        self.inner
-            .borrow_mut()
-            .headers
-            .insert(name.into(), value.into());
+        if let Some((name, value)) = header_pair(name.into(), value.into()) {
+            self.inner().headers.insert(name, value);
+        }
+        self
+    }
+
+    /// Add a response header, keeping earlier values, e.g. to send several
+    /// `Link` or `Vary` headers.
+    ///
+    /// Headers with an invalid name or value are dropped, with a warning in
+    /// dev mode.
+    pub fn append_header(&self, name: impl Into<String>, value: impl Into<String>) -> &Self {
+        if let Some((name, value)) = header_pair(name.into(), value.into()) {
+            self.inner().headers.append(name, value);
+        }
         self
     }
 
+    /// Set the `Cache-Control` header.
+    ///
+    /// # Example
+    /// ```ignore
+    /// res.set_cache_control("public, max-age=3600");
+    /// ```
+    pub fn set_cache_control(&self, value: impl Into<String>) -> &Self {
+        self.set_header("Cache-Control", value)
+    }
+
+    /// Set the `Content-Type` header, overriding the one the finalizer
+    /// would choose.
+    pub fn set_content_type(&self, content_type: impl Into<String>) -> &Self {
+        self.set_header("Content-Type", content_type)
+    }
+
     /// Set the response status code
     pub fn set_status(&self, status: StatusCode) -> &Self {
-        self.inner.borrow_mut().status = Some(status);
+        self.inner().status = Some(status);
         self
     }
 
-    /// Set a cookie on the response
+    /// Set the `ETag` identifying this version of the content.
+    ///
+    /// Quotes are added if missing; prefix the tag with `W/` for a weak tag.
+    /// Set it before doing expensive work and check [`Res::is_fresh`] to
+    /// skip rendering when the client's copy is current.
+    pub fn set_etag(&self, etag: impl AsRef<str>) -> &Self {
+        let etag = etag.as_ref();
+        let (weak, tag) = match etag.strip_prefix("W/") {
+            Some(tag) => ("W/", tag),
+            None => ("", etag),
+        };
+        let tag = tag.trim_matches('"');
+        self.set_header("ETag", format!("{}\"{}\"", weak, tag))
+    }
+
+    /// Set the `Last-Modified` time of the content.
+    pub fn set_last_modified(&self, time: std::time::SystemTime) -> &Self {
+        self.set_header(
+            "Last-Modified",
+            httpdate::fmt_http_date(files::truncate_to_secs(time)),
+        )
+    }
+
+    /// Whether the client's cached copy matches the `ETag` or
+    /// `Last-Modified` set on this response, so the handler can answer with
+    /// [`Res::not_modified`] instead of rendering.
+    ///
+    /// # Example
+    /// ```ignore
+    /// pub async fn get(state: AppState, req: Req, res: Res, id: String) -> Result<Res, Error> {
+    ///     let updated_at = post_updated_at(&state.db, &id).await?;
+    ///     res.set_last_modified(updated_at);
+    ///     if res.is_fresh() {
+    ///         return Ok(res.not_modified());
+    ///     }
+    ///     let post = load_post_with_comments(&state.db, &id).await?;
+    ///     Ok(res.html(render_post(&post)))
+    /// }
+    /// ```
+    pub fn is_fresh(&self) -> bool {
+        if !matches!(self.ctx.method, Method::GET | Method::HEAD) {
+            return false;
+        }
+        let header = |name: HeaderName| {
+            self.inner()
+                .headers
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(str::to_string)
+        };
+        let validators = Validators {
+            etag: header(header::ETAG),
+            last_modified: header(header::LAST_MODIFIED)
+                .and_then(|v| httpdate::parse_http_date(&v).ok()),
+        };
+        if validators.etag.is_none() && validators.last_modified.is_none() {
+            return false;
+        }
+        files::is_not_modified(&self.ctx.headers, &validators)
+    }
+
+    /// Finalize as an empty 304 Not Modified response.
+    pub fn not_modified(&self) -> Self {
+        self.inner().status = Some(StatusCode::NOT_MODIFIED);
+        self.finalize(ResBody::Raw(Vec::new()))
+    }
+
+    /// Set a cookie on the response.
+    ///
+    /// Uses `Path=/`, `HttpOnly` and `SameSite=Lax`. Use
+    /// [`add_cookie`](Self::add_cookie) for other options.
     pub fn set_cookie(&self, name: impl Into<String>, value: impl Into<String>) -> &Self {
-        self.inner.borrow_mut().cookies.push((
-            name.into(),
-            value.into(),
-            CookieOptions::default(),
-        ));
+        self.add_cookie(Cookie::new(name.into(), value.into()))
+    }
+
+    /// Add a cookie built with [`Cookie::build`].
+    ///
+    /// Attributes left unset default to `Path=/`, `HttpOnly` and
+    /// `SameSite=Lax`; set them explicitly to opt out. Names and values are
+    /// percent-encoded, so any string is safe to store.
+    ///
+    /// # Example
+    /// ```ignore
+    /// use rejoice::{Cookie, SameSite, time::Duration};
+    ///
+    /// res.add_cookie(
+    ///     Cookie::build(("session", token))
+    ///         .domain("example.com")
+    ///         .max_age(Duration::days(7))
+    ///         .secure(true)
+    ///         .same_site(SameSite::Strict),
+    /// )
+    /// ```
+    pub fn add_cookie<'c>(&self, cookie: impl Into<Cookie<'c>>) -> &Self {
+        let mut cookie = cookie.into().into_owned();
+        if cookie.path().is_none() {
+            cookie.set_path("/");
+        }
+        if cookie.http_only().is_none() {
+            cookie.set_http_only(true);
+        }
+        if cookie.same_site().is_none() {
+            cookie.set_same_site(SameSite::Lax);
+        }
+        self.inner().cookies.push(cookie);
         self
     }
 
@@ -93,6 +276,7 @@ impl Res {
     /// ```ignore
     /// res.set_cookie_with_options("session", token, Some("/"), Some(3600), true, true, Some("Strict"))
     /// ```
+    #[deprecated(note = "use `Res::add_cookie` with `Cookie::build` instead")]
     #[allow(clippy::too_many_arguments)]
     pub fn set_cookie_with_options(
         &self,
@@ -104,132 +288,517 @@ impl Res {
         secure: bool,
         same_site: Option<&str>,
     ) -> &Self {
-        self.inner.borrow_mut().cookies.push((
-            name.into(),
-            value.into(),
-            CookieOptions {
-                path: path.map(|s| s.to_string()),
-                max_age,
-                http_only,
-                secure,
-                same_site: same_site.map(|s| s.to_string()),
-            },
-        ));
+        let mut cookie = Cookie::new(name.into(), value.into());
+        if let Some(path) = path {
+            cookie.set_path(path.to_string());
+        }
+        if let Some(max_age) = max_age {
+            cookie.set_max_age(cookie::time::Duration::seconds(max_age));
+        }
+        cookie.set_http_only(http_only);
+        cookie.set_secure(secure);
+        cookie.set_same_site(same_site.and_then(|s| match s.to_ascii_lowercase().as_str() {
+            "strict" => Some(SameSite::Strict),
+            "lax" => Some(SameSite::Lax),
+            "none" => Some(SameSite::None),
+            _ => None,
+        }));
+        self.inner().cookies.push(cookie);
         self
     }
 
-    /// Delete a cookie by setting it to expire immediately
-    pub fn delete_cookie(&self, name: impl Into<String>) -> &Self {
-        self.inner.borrow_mut().cookies.push((
-            name.into(),
-            String::new(),
-            CookieOptions {
-                path: Some("/".to_string()),
-                max_age: Some(0),
-                http_only: true,
-                secure: false,
-                same_site: Some("Lax".to_string()),
-            },
-        ));
+    /// Set a cookie whose value is signed with the app's secret key.
+    ///
+    /// The value stays readable by the client but can't be modified; read it
+    /// back with `req.cookies.get_signed`.
+    ///
+    /// # Panics
+    /// Panics if no secret key is configured with `App::secret_key`.
+    pub fn set_signed_cookie(&self, name: impl Into<String>, value: impl Into<String>) -> &Self {
+        self.add_signed_cookie(Cookie::new(name.into(), value.into()))
+    }
+
+    /// Add a signed cookie built with [`Cookie::build`].
+    ///
+    /// # Panics
+    /// Panics if no secret key is configured with `App::secret_key`.
+    pub fn add_signed_cookie<'c>(&self, cookie: impl Into<Cookie<'c>>) -> &Self {
+        let mut cookie = cookie.into().into_owned();
+        cookie.set_value(self.keys().sign(cookie.name(), cookie.value()));
+        self.add_cookie(cookie)
+    }
+
+    /// Set a cookie whose value is encrypted with the app's secret key.
+    ///
+    /// The value can be neither read nor modified by the client; read it back
+    /// with `req.cookies.get_private`.
+    ///
+    /// # Panics
+    /// Panics if no secret key is configured with `App::secret_key`.
+    pub fn set_private_cookie(&self, name: impl Into<String>, value: impl Into<String>) -> &Self {
+        self.add_private_cookie(Cookie::new(name.into(), value.into()))
+    }
+
+    /// Add a private cookie built with [`Cookie::build`].
+    ///
+    /// # Panics
+    /// Panics if no secret key is configured with `App::secret_key`.
+    pub fn add_private_cookie<'c>(&self, cookie: impl Into<Cookie<'c>>) -> &Self {
+        let mut cookie = cookie.into().into_owned();
+        cookie.set_value(self.keys().encrypt(cookie.name(), cookie.value()));
+        self.add_cookie(cookie)
+    }
+
+    fn keys(&self) -> &SecretKeys {
+        self.ctx
+            .keys
+            .as_deref()
+            .expect("Signed and private cookies require a secret key; call App::secret_key")
+    }
+
+    /// Show a message on the next page the user sees, typically after a
+    /// redirect. Read it there with `req.flashes()`.
+    ///
+    /// `level` is free-form, e.g. `"success"` or `"error"`, for styling.
+    /// Several messages can be flashed at once.
+    ///
+    /// # Example
+    /// ```ignore
+    /// res.flash("success", "Post published").redirect_see_other("/posts")
+    /// ```
+    pub fn flash(&self, level: impl Into<String>, message: impl Into<String>) -> &Self {
+        let mut inner = self.inner();
+        let mut messages = match inner
+            .cookies
+            .iter()
+            .position(|c| c.name() == flash::COOKIE_NAME)
+        {
+            Some(i) => flash::decode(inner.cookies.remove(i).value(), self.ctx.keys.as_deref()),
+            None => Vec::new(),
+        };
+        messages.push(Flash {
+            level: level.into(),
+            message: message.into(),
+        });
+        inner
+            .cookies
+            .push(flash::cookie(&messages, self.ctx.keys.as_deref()));
+        drop(inner);
         self
     }
 
+    /// Delete a cookie set with `Path=/` by setting it to expire immediately.
+    ///
+    /// Use [`expire_cookie`](Self::expire_cookie) for cookies set with
+    /// another path or a domain.
+    pub fn delete_cookie(&self, name: impl Into<String>) -> &Self {
+        self.expire_cookie(Cookie::from(name.into()))
+    }
+
+    /// Delete a cookie by setting it to expire immediately.
+    ///
+    /// Browsers only remove a cookie when the path and domain match the ones it
+    /// was set with, so `cookie` must carry them if they aren't the defaults.
+    ///
+    /// # Example
+    /// ```ignore
+    /// res.expire_cookie(Cookie::build("prefs").path("/app").domain("example.com"));
+    /// ```
+    pub fn expire_cookie<'c>(&self, cookie: impl Into<Cookie<'c>>) -> &Self {
+        let mut cookie = cookie.into().into_owned();
+        cookie.make_removal();
+        self.add_cookie(cookie)
+    }
+
     /// Finalize as an HTML response
     pub fn html(&self, markup: Markup) -> Self {
-        self.inner.borrow_mut().body = Some(ResBody::Html(markup.into_string()));
-        if self.inner.borrow().status.is_none() {
-            self.inner.borrow_mut().status = Some(StatusCode::OK);
-        }
-        self.clone()
+        self.finalize(ResBody::Html(markup.into_string()))
     }
 
     /// Finalize as a JSON response
     pub fn json<T: Serialize>(&self, data: &T) -> Self {
         let json_string = serde_json::to_string(data).unwrap_or_else(|_| "null".to_string());
-        self.inner.borrow_mut().body = Some(ResBody::Json(json_string));
-        if self.inner.borrow().status.is_none() {
-            self.inner.borrow_mut().status = Some(StatusCode::OK);
+        self.finalize(ResBody::Json(json_string))
+    }
+
+    /// Finalize as HTML or JSON depending on the request's `Accept` header.
+    ///
+    /// Clients that prefer `application/json` get `data` serialized as JSON;
+    /// everyone else gets `markup` (wrapped in layouts as usual).
+    ///
+    /// # Example
+    /// ```ignore
+    /// pub async fn get(req: Req, res: Res) -> Res {
+    ///     let user = load_user().await;
+    ///     res.negotiate(html! { h1 { (user.name) } }, &user)
+    /// }
+    /// ```
+    pub fn negotiate<T: Serialize>(&self, markup: Markup, data: &T) -> Self {
+        self.append_header("Vary", "Accept");
+        if self.prefers_json() {
+            self.json(data)
+        } else {
+            self.html(markup)
         }
-        self.clone()
+    }
+
+    /// Whether the request's `Accept` header prefers JSON over HTML.
+    pub(crate) fn prefers_json(&self) -> bool {
+        crate::negotiate::media_type(&self.ctx.headers, &["text/html", "application/json"])
+            == Some("application/json")
     }
 
     /// Finalize as a redirect (302 Found)
     pub fn redirect(&self, url: impl Into<String>) -> Self {
-        self.inner.borrow_mut().body = Some(ResBody::Redirect(url.into(), false));
-        self.clone()
+        self.redirect_with_status(url, StatusCode::FOUND)
     }
 
     /// Finalize as a permanent redirect (301 Moved Permanently)
     pub fn redirect_permanent(&self, url: impl Into<String>) -> Self {
-        self.inner.borrow_mut().body = Some(ResBody::Redirect(url.into(), true));
+        self.redirect_with_status(url, StatusCode::MOVED_PERMANENTLY)
+    }
+
+    /// Finalize as a 303 See Other redirect, which the browser follows with
+    /// a GET. Use it after handling a form POST.
+    ///
+    /// # Example
+    /// ```ignore
+    /// pub async fn post(state: AppState, req: Req, res: Res) -> Result<Res, Error> {
+    ///     let post = create_post(&state.db, req.body.as_form()?).await?;
+    ///     Ok(res.redirect_see_other(format!("/posts/{}", post.id)))
+    /// }
+    /// ```
+    pub fn redirect_see_other(&self, url: impl Into<String>) -> Self {
+        self.redirect_with_status(url, StatusCode::SEE_OTHER)
+    }
+
+    /// Finalize as a 307 Temporary Redirect, which the client follows with
+    /// the same method and body.
+    pub fn redirect_temporary(&self, url: impl Into<String>) -> Self {
+        self.redirect_with_status(url, StatusCode::TEMPORARY_REDIRECT)
+    }
+
+    /// Finalize as a 308 Permanent Redirect, which the client follows with
+    /// the same method and body.
+    pub fn redirect_permanent_keep_method(&self, url: impl Into<String>) -> Self {
+        self.redirect_with_status(url, StatusCode::PERMANENT_REDIRECT)
+    }
+
+    /// Finalize as a redirect with the given 3xx status.
+    ///
+    /// # Panics
+    /// Panics if `status` is not a redirection status.
+    pub fn redirect_with_status(&self, url: impl Into<String>, status: StatusCode) -> Self {
+        assert!(
+            status.is_redirection(),
+            "{} is not a redirect status; use Res::set_status for other responses",
+            status
+        );
+        self.inner().body = Some(ResBody::Redirect(url.into(), status));
         self.clone()
     }
 
-    /// Finalize as a raw byte response
+    /// Redirect back to the page the request came from (303 See Other), or
+    /// to `fallback` if the `Referer` is missing or points to another site.
+    ///
+    /// # Example
+    /// ```ignore
+    /// pub async fn post(req: Req, res: Res) -> Res {
+    ///     toggle_favorite(&req).await;
+    ///     res.redirect_back(&req, "/")
+    /// }
+    /// ```
+    pub fn redirect_back(&self, req: &Req, fallback: impl Into<String>) -> Self {
+        match redirect::same_origin_referer(&req.headers, &req.uri) {
+            Some(target) => self.redirect_see_other(target),
+            None => self.redirect_see_other(fallback),
+        }
+    }
+
+    /// Redirect to `target` (303 See Other) if it's a path on this site, or
+    /// to `fallback` otherwise.
+    ///
+    /// Use it when the target comes from the request, such as a `?next=`
+    /// parameter, so it can't be used to send users to another site. See
+    /// [`is_safe_redirect`](crate::is_safe_redirect).
+    ///
+    /// # Example
+    /// ```ignore
+    /// res.redirect_safe(&form.next, "/dashboard")
+    /// ```
+    pub fn redirect_safe(&self, target: &str, fallback: impl Into<String>) -> Self {
+        if redirect::is_safe_redirect(target) {
+            self.redirect_see_other(target)
+        } else {
+            self.redirect_see_other(fallback)
+        }
+    }
+
+    /// Finalize as a raw byte response.
+    ///
+    /// Sent as `application/octet-stream` unless a content type was set.
     pub fn raw(&self, body: impl Into<Vec<u8>>) -> Self {
-        self.inner.borrow_mut().body = Some(ResBody::Raw(body.into()));
-        if self.inner.borrow().status.is_none() {
-            self.inner.borrow_mut().status = Some(StatusCode::OK);
+        self.set_default_content_type("application/octet-stream");
+        self.finalize(ResBody::Raw(body.into()))
+    }
+
+    /// Finalize as a plain text response
+    pub fn text(&self, body: impl Into<String>) -> Self {
+        self.with_content_type("text/plain; charset=utf-8", body.into())
+    }
+
+    /// Finalize as an XML response, e.g. a sitemap or feed
+    pub fn xml(&self, body: impl Into<String>) -> Self {
+        self.with_content_type("application/xml; charset=utf-8", body.into())
+    }
+
+    /// Finalize as a response with the given content type.
+    ///
+    /// # Example
+    /// ```ignore
+    /// res.with_content_type("text/csv; charset=utf-8", csv)
+    /// ```
+    pub fn with_content_type(&self, content_type: &str, body: impl Into<Vec<u8>>) -> Self {
+        self.set_content_type(content_type);
+        self.finalize(ResBody::Raw(body.into()))
+    }
+
+    /// Make the browser download the response as `filename` instead of
+    /// displaying it.
+    ///
+    /// Non-ASCII names are encoded so every browser gets a usable name.
+    pub fn set_attachment(&self, filename: &str) -> &Self {
+        self.set_header(
+            "Content-Disposition",
+            files::content_disposition("attachment", filename),
+        )
+    }
+
+    /// Finalize as a download of in-memory bytes, e.g. a generated PDF.
+    ///
+    /// The content type is guessed from `filename` unless already set, and
+    /// single `Range` requests are answered with 206 Partial Content.
+    ///
+    /// # Example
+    /// ```ignore
+    /// let pdf = render_invoice(&invoice);
+    /// res.attachment(pdf, &format!("Rechnung-{}.pdf", invoice.number))
+    /// ```
+    pub fn attachment(&self, bytes: impl Into<Vec<u8>>, filename: &str) -> Self {
+        let bytes = bytes.into();
+        self.set_attachment(filename);
+        self.set_default_content_type(&files::content_type_for(filename));
+        self.set_header("Accept-Ranges", "bytes");
+
+        match files::select(&self.ctx.headers, &self.ctx.method, bytes.len() as u64, None) {
+            Selection::Partial(start, end) => {
+                self.partial_content(start, end, bytes.len() as u64);
+                self.finalize(ResBody::Raw(bytes[start as usize..=end as usize].to_vec()))
+            }
+            Selection::Unsatisfiable => self.range_not_satisfiable(bytes.len() as u64),
+            Selection::Full | Selection::NotModified => self.finalize(ResBody::Raw(bytes)),
         }
-        self.clone()
+    }
+
+    /// Finalize by streaming a file from disk.
+    ///
+    /// The content type is guessed from the extension unless already set.
+    /// `ETag` and `Last-Modified` are sent so browsers can revalidate with a
+    /// 304 Not Modified, and single `Range` requests get 206 Partial Content,
+    /// so downloads can resume and media can seek. Call
+    /// [`Res::set_attachment`] first to download rather than display the
+    /// file. Responds with 404 if the file doesn't exist.
+    ///
+    /// Don't pass user input as the path without checking it, or clients
+    /// can read any file the server can.
+    ///
+    /// # Example
+    /// ```ignore
+    /// pub async fn get(req: Req, res: Res, id: String) -> Res {
+    ///     let path = format!("exports/{}.csv", id.parse::<u32>().unwrap_or_default());
+    ///     res.set_attachment("export.csv").file(path).await
+    /// }
+    /// ```
+    pub async fn file(&self, path: impl AsRef<std::path::Path>) -> Self {
+        let path = path.as_ref();
+        let opened = match tokio::fs::File::open(path).await {
+            Ok(file) => file.metadata().await.map(|meta| (file, meta)),
+            Err(e) => Err(e),
+        };
+        let (mut file, meta) = match opened {
+            Ok((file, meta)) if meta.is_file() => (file, meta),
+            _ => {
+                // Don't download the error page under the file's name
+                self.inner().headers.remove(header::CONTENT_DISPOSITION);
+                return self.not_found("File not found");
+            }
+        };
+
+        let len = meta.len();
+        let validators = Validators::for_file(len, meta.modified().ok());
+        if let Some(etag) = &validators.etag {
+            self.set_header("ETag", etag.clone());
+        }
+        if let Some(modified) = validators.last_modified {
+            self.set_header("Last-Modified", httpdate::fmt_http_date(modified));
+        }
+        self.set_header("Accept-Ranges", "bytes");
+        self.set_default_content_type(&files::content_type_for(&path.to_string_lossy()));
+
+        let (start, count) =
+            match files::select(&self.ctx.headers, &self.ctx.method, len, Some(&validators)) {
+                Selection::NotModified => return self.not_modified(),
+                Selection::Unsatisfiable => return self.range_not_satisfiable(len),
+                Selection::Partial(start, end) => {
+                    self.partial_content(start, end, len);
+                    (start, end - start + 1)
+                }
+                Selection::Full => (0, len),
+            };
+
+        if start > 0 && file.seek(std::io::SeekFrom::Start(start)).await.is_err() {
+            return self.internal_error("Failed to read file");
+        }
+        self.set_header("Content-Length", count.to_string());
+        self.finalize(ResBody::Stream(StreamBody::new(ReaderStream::new(
+            file.take(count),
+        ))))
+    }
+
+    /// Set the content type unless the handler already chose one.
+    fn set_default_content_type(&self, content_type: &str) {
+        let mut inner = self.inner();
+        if !inner.headers.contains_key(header::CONTENT_TYPE)
+            && let Ok(value) = HeaderValue::from_str(content_type)
+        {
+            inner.headers.insert(header::CONTENT_TYPE, value);
+        }
+    }
+
+    fn partial_content(&self, start: u64, end: u64, len: u64) {
+        self.set_header("Content-Range", format!("bytes {}-{}/{}", start, end, len));
+        self.inner().status = Some(StatusCode::PARTIAL_CONTENT);
+    }
+
+    fn range_not_satisfiable(&self, len: u64) -> Self {
+        self.set_header("Content-Range", format!("bytes */{}", len));
+        self.inner().status = Some(StatusCode::RANGE_NOT_SATISFIABLE);
+        self.finalize(ResBody::Raw(Vec::new()))
+    }
+
+    /// Finalize as a streamed response, sending each chunk as it's produced.
+    ///
+    /// Streams of `Markup` are HTML and go through layouts: the layout is
+    /// rendered first and its shell flushed before the page content, so slow
+    /// pages still get a fast first byte. Other chunk types (`String`,
+    /// `Bytes`, `Vec<u8>`) skip layouts. Yield `Result`s to end the stream
+    /// early on error.
+    ///
+    /// # Example
+    /// ```ignore
+    /// pub async fn get(state: AppState, req: Req, res: Res) -> Res {
+    ///     let header = stream::once(async { html! { h1 { "Dashboard" } } });
+    ///     let report = stream::once(async move {
+    ///         let stats = load_stats(&state.db).await;
+    ///         html! { p { (stats.total) " orders" } }
+    ///     });
+    ///     res.stream(header.chain(report))
+    /// }
+    /// ```
+    pub fn stream<S, C>(&self, chunks: S) -> Self
+    where
+        S: Stream<Item = C> + Send + 'static,
+        C: IntoChunk,
+    {
+        self.finalize(ResBody::Stream(StreamBody::new(csrf::with_session(chunks))))
+    }
+
+    /// Finalize as a Server-Sent Events stream.
+    ///
+    /// A keep-alive comment is sent whenever the stream is idle for
+    /// [`sse::KEEP_ALIVE`](crate::sse::KEEP_ALIVE), so proxies don't close
+    /// the connection. Use [`Hub::subscribe`](crate::sse::Hub::subscribe) to
+    /// broadcast to every client.
+    ///
+    /// # Example
+    /// ```ignore
+    /// pub async fn get(req: Req, res: Res) -> Res {
+    ///     let ticks = IntervalStream::new(tokio::time::interval(Duration::from_secs(1)))
+    ///         .enumerate()
+    ///         .map(|(i, _)| Event::new(i.to_string()).event("tick"));
+    ///     res.sse(ticks)
+    /// }
+    /// ```
+    pub fn sse<S>(&self, events: S) -> Self
+    where
+        S: Stream<Item = Event> + Send + 'static,
+    {
+        self.set_cache_control("no-cache");
+        // Stops nginx from buffering the stream
+        self.set_header("X-Accel-Buffering", "no");
+        self.finalize(ResBody::Stream(StreamBody::from_stream(
+            "text/event-stream",
+            sse::encode(events),
+        )))
     }
 
     // === Error Response Helpers ===
 
-    /// Return a 400 Bad Request response with HTML message
+    /// Finalize as an error response with any status, rendered by the app's
+    /// `ErrorPages`.
+    ///
+    /// Clients that prefer JSON get `{ "error": message }`; everyone else
+    /// gets the HTML page, wrapped in layouts as usual.
+    pub fn error(&self, status: StatusCode, message: &str) -> Self {
+        self.inner().status = Some(status);
+        self.append_header("Vary", "Accept");
+
+        let pages = self.ctx.error_pages.as_deref().unwrap_or(&DefaultErrorPages);
+        if self.prefers_json() {
+            self.json(&pages.json(status, message))
+        } else {
+            self.html(error::render_page(pages, status, message))
+        }
+    }
+
+    /// Return a 400 Bad Request error response
     pub fn bad_request(&self, message: &str) -> Self {
-        self.inner.borrow_mut().status = Some(StatusCode::BAD_REQUEST);
-        self.html(maud::html! {
-            h1 { "Bad Request" }
-            p { (message) }
-        })
+        self.error(StatusCode::BAD_REQUEST, message)
     }
 
-    /// Return a 401 Unauthorized response with HTML message
+    /// Return a 401 Unauthorized error response
     pub fn unauthorized(&self, message: &str) -> Self {
-        self.inner.borrow_mut().status = Some(StatusCode::UNAUTHORIZED);
-        self.html(maud::html! {
-            h1 { "Unauthorized" }
-            p { (message) }
-        })
+        self.error(StatusCode::UNAUTHORIZED, message)
     }
 
-    /// Return a 403 Forbidden response with HTML message
+    /// Return a 403 Forbidden error response
     pub fn forbidden(&self, message: &str) -> Self {
-        self.inner.borrow_mut().status = Some(StatusCode::FORBIDDEN);
-        self.html(maud::html! {
-            h1 { "Forbidden" }
-            p { (message) }
-        })
+        self.error(StatusCode::FORBIDDEN, message)
     }
 
-    /// Return a 404 Not Found response with HTML message
+    /// Return a 404 Not Found error response
     pub fn not_found(&self, message: &str) -> Self {
-        self.inner.borrow_mut().status = Some(StatusCode::NOT_FOUND);
-        self.html(maud::html! {
-            h1 { "Not Found" }
-            p { (message) }
-        })
+        self.error(StatusCode::NOT_FOUND, message)
     }
 
-    /// Return a 500 Internal Server Error response with HTML message
+    /// Return a 500 Internal Server Error response
     pub fn internal_error(&self, message: &str) -> Self {
-        self.inner.borrow_mut().status = Some(StatusCode::INTERNAL_SERVER_ERROR);
-        self.html(maud::html! {
-            h1 { "Internal Server Error" }
-            p { (message) }
-        })
+        self.error(StatusCode::INTERNAL_SERVER_ERROR, message)
     }
 
     /// Check if this response is HTML (for layout wrapping)
     pub fn is_html(&self) -> bool {
-        matches!(self.inner.borrow().body, Some(ResBody::Html(_)))
+        match &self.inner().body {
+            Some(ResBody::Html(_)) => true,
+            Some(ResBody::Stream(stream)) => stream.is_html(),
+            _ => false,
+        }
     }
 
     /// Extract the HTML content for layout wrapping.
     /// Returns None if this is not an HTML response.
     pub fn take_html(&self) -> Option<String> {
-        let mut inner = self.inner.borrow_mut();
+        let mut inner = self.inner();
         match &inner.body {
             Some(ResBody::Html(_)) => {
                 if let Some(ResBody::Html(html)) = inner.body.take() {
@@ -244,29 +813,66 @@ impl Res {
 
     /// Set HTML content (used by layout wrapping)
     pub fn set_html(&self, html: String) {
-        self.inner.borrow_mut().body = Some(ResBody::Html(html));
+        self.inner().body = Some(ResBody::Html(html));
     }
 
-    fn build_cookie_header(name: &str, value: &str, options: &CookieOptions) -> String {
-        let mut cookie = format!("{}={}", name, value);
-
-        if let Some(path) = &options.path {
-            cookie.push_str(&format!("; Path={}", path));
-        }
-        if let Some(max_age) = options.max_age {
-            cookie.push_str(&format!("; Max-Age={}", max_age));
-        }
-        if options.http_only {
-            cookie.push_str("; HttpOnly");
-        }
-        if options.secure {
-            cookie.push_str("; Secure");
+    /// Whether to send the page without its layouts because the request is
+    /// partial (used by layout wrapping).
+    ///
+    /// Adds the partial header to `Vary`, since the same URL renders
+    /// differently depending on it.
+    #[doc(hidden)]
+    pub fn skip_layouts(&self, req: &Req) -> bool {
+        self.append_header("Vary", req.partial_header.0.as_str());
+        let partial = req.is_partial();
+        if partial {
+            self.inner().partial = true;
         }
-        if let Some(same_site) = &options.same_site {
-            cookie.push_str(&format!("; SameSite={}", same_site));
+        partial
+    }
+
+    /// Mark this response as a page fragment (used by generated route
+    /// wrappers), so it's sent without injected scripts and styles.
+    #[doc(hidden)]
+    pub fn into_fragment(self) -> Self {
+        self.inner().partial = true;
+        self
+    }
+
+    /// Take the HTML to pass to a layout as `children` (used by layout wrapping).
+    ///
+    /// Streamed pages stay in place and the layout gets a placeholder, which
+    /// [`Res::absorb_layout`] later splits the layout's output around.
+    #[doc(hidden)]
+    pub fn layout_children(&self) -> Markup {
+        if let Some(ResBody::Stream(_)) = &self.inner().body {
+            return PreEscaped(SLOT.to_string());
         }
+        PreEscaped(self.take_html().unwrap_or_default())
+    }
 
-        cookie
+    /// Move a layout's output into this page response (used by layout wrapping).
+    ///
+    /// The layout's HTML becomes the body and its headers and cookies are
+    /// added, keeping the page's `Vary` values. The page keeps its status
+    /// unless the layout set a non-200 one.
+    #[doc(hidden)]
+    pub fn absorb_layout(&self, layout: &Res) {
+        let layout = std::mem::take(&mut *layout.inner());
+        let mut inner = self.inner();
+        inner.body = match (&inner.body, layout.body) {
+            // A streamed page is spliced into the layout's shell
+            (Some(ResBody::Stream(page)), Some(ResBody::Html(shell))) => match page.wrap(&shell) {
+                Some(wrapped) => Some(ResBody::Stream(wrapped)),
+                None => Some(ResBody::Html(shell)),
+            },
+            (_, body) => body,
+        };
+        merge_headers(&mut inner.headers, layout.headers);
+        inner.cookies.extend(layout.cookies);
+        if let Some(status) = layout.status.filter(|s| *s != StatusCode::OK) {
+            inner.status = Some(status);
+        }
     }
 }
 
@@ -276,23 +882,33 @@ impl Default for Res {
     }
 }
 
+// Handlers hold `&Res` across `.await`, so their futures are only `Send` if
+// `Res` is `Sync`
+const _: fn() = || {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Res>();
+};
+
 impl Clone for Res {
     fn clone(&self) -> Self {
-        let inner = self.inner.borrow();
+        let inner = self.inner();
         Self {
-            inner: RefCell::new(ResInner {
+            inner: Mutex::new(ResInner {
                 status: inner.status,
                 headers: inner.headers.clone(),
                 cookies: inner.cookies.clone(),
                 body: inner.body.clone(),
+                partial: inner.partial,
             }),
+            ctx: self.ctx.clone(),
         }
     }
 }
 
 impl IntoResponse for Res {
     fn into_response(self) -> axum::response::Response {
-        let inner = self.inner.into_inner();
+        let inner = self.inner.into_inner().unwrap_or_else(|e| e.into_inner());
+        let streaming = matches!(inner.body, Some(ResBody::Stream(_)));
 
         let (status, content_type, body) = match inner.body {
             Some(ResBody::Html(html)) => (
@@ -305,38 +921,40 @@ impl IntoResponse for Res {
                 Some("application/json"),
                 Body::from(json),
             ),
-            Some(ResBody::Redirect(url, permanent)) => {
-                let status = if permanent {
-                    StatusCode::MOVED_PERMANENTLY
-                } else {
-                    StatusCode::FOUND
-                };
+            Some(ResBody::Redirect(url, status)) => {
                 let mut response = axum::response::Response::builder()
                     .status(status)
-                    .header(header::LOCATION, url)
                     .body(Body::empty())
                     .unwrap();
+                match HeaderValue::from_str(&url) {
+                    Ok(location) => {
+                        response.headers_mut().insert(header::LOCATION, location);
+                    }
+                    Err(_) => {
+                        if std::env::var("REJOICE_DEV").is_ok() {
+                            eprintln!(
+                                "{} can't redirect to {:?} because it isn't a valid header value",
+                                "error:".red().bold(),
+                                url
+                            );
+                        }
+                        *response.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+                    }
+                }
 
                 // Add cookies to redirect response
-                for (name, value, options) in inner.cookies {
-                    let cookie_header = Self::build_cookie_header(&name, &value, &options);
-                    response.headers_mut().append(
-                        header::SET_COOKIE,
-                        HeaderValue::from_str(&cookie_header).unwrap(),
-                    );
-                }
+                append_cookies(&mut response, &inner.cookies);
 
                 // Add custom headers
-                for (name, value) in inner.headers {
-                    if let (Ok(name), Ok(value)) =
-                        (name.parse::<HeaderName>(), value.parse::<HeaderValue>())
-                    {
-                        response.headers_mut().insert(name, value);
-                    }
-                }
+                merge_headers(response.headers_mut(), inner.headers);
 
                 return response;
             }
+            Some(ResBody::Stream(stream)) => (
+                inner.status.unwrap_or(StatusCode::OK),
+                Some(stream.content_type),
+                stream.into_body(),
+            ),
             Some(ResBody::Raw(bytes)) => (
                 inner.status.unwrap_or(StatusCode::OK),
                 None,
@@ -354,6 +972,13 @@ impl IntoResponse for Res {
             .body(body)
             .unwrap();
 
+        if streaming {
+            response.extensions_mut().insert(Streaming);
+        }
+        if inner.partial {
+            response.extensions_mut().insert(Partial);
+        }
+
         // Set content type if we have one
         if let Some(ct) = content_type {
             response
@@ -362,24 +987,71 @@ impl IntoResponse for Res {
         }
 
         // Add cookies
-        for (name, value, options) in inner.cookies {
-            let cookie_header = Self::build_cookie_header(&name, &value, &options);
-            response.headers_mut().append(
-                header::SET_COOKIE,
-                HeaderValue::from_str(&cookie_header).unwrap(),
-            );
-        }
-
-        // Add custom headers
-        for (name, value) in inner.headers {
-            if let (Ok(name), Ok(value)) =
-                (name.parse::<HeaderName>(), value.parse::<HeaderValue>())
-            {
-                response.headers_mut().insert(name, value);
+        append_cookies(&mut response, &inner.cookies);
+
+        // Add custom headers, replacing defaults of the same name
+        merge_headers(response.headers_mut(), inner.headers);
+
+        response
+    }
+}
+
+/// Headers whose values are combined rather than replaced when merging.
+const LIST_HEADERS: [HeaderName; 3] = [header::SET_COOKIE, header::VARY, header::LINK];
+
+/// Add `source` to `target`. `Set-Cookie`, `Vary` and `Link` values are
+/// added to the ones already there; other headers replace them.
+fn merge_headers(target: &mut HeaderMap, source: HeaderMap) {
+    let mut current = None;
+    for (name, value) in source {
+        // Further values of the same header come without a name
+        if let Some(name) = name {
+            if !LIST_HEADERS.contains(&name) {
+                target.remove(&name);
             }
+            current = Some(name);
         }
+        if let Some(name) = &current {
+            target.append(name, value);
+        }
+    }
+}
 
-        response
+/// Parse a header set by a handler, warning in dev mode if it's invalid.
+fn header_pair(name: String, value: String) -> Option<(HeaderName, HeaderValue)> {
+    let problem = match (HeaderName::try_from(&name), HeaderValue::try_from(&value)) {
+        (Ok(name), Ok(value)) => return Some((name, value)),
+        (Err(_), _) => "its name is invalid",
+        (_, Err(_)) => "its value contains invalid characters",
+    };
+    if std::env::var("REJOICE_DEV").is_ok() {
+        eprintln!(
+            "{} dropped header {:?} because {}",
+            "warning:".yellow().bold(),
+            name,
+            problem
+        );
+    }
+    None
+}
+
+/// Append `Set-Cookie` headers, skipping cookies that can't form a valid header.
+fn append_cookies(response: &mut axum::response::Response, cookies: &[Cookie<'static>]) {
+    for cookie in cookies {
+        match HeaderValue::from_str(&cookie.encoded().to_string()) {
+            Ok(value) => {
+                response.headers_mut().append(header::SET_COOKIE, value);
+            }
+            Err(_) => {
+                if std::env::var("REJOICE_DEV").is_ok() {
+                    eprintln!(
+                        "{} dropped cookie {:?} because it can't be sent as a header",
+                        "warning:".yellow().bold(),
+                        cookie.name()
+                    );
+                }
+            }
+        }
     }
 }
 
@@ -389,7 +1061,7 @@ where
 {
     type Rejection = std::convert::Infallible;
 
-    async fn from_request_parts(_parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
-        Ok(Res::new())
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(Res::for_request(parts))
     }
 }
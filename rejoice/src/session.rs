@@ -0,0 +1,615 @@
+//! Server-side sessions.
+//!
+//! Enable sessions with `App::sessions`, then read and write values through
+//! `req.session()`. Only the session ID is stored in the browser; the data
+//! lives in a [`SessionStore`].
+//!
+//! ```ignore
+//! let app = App::new(8080, create_router())
+//!     .sessions(MemoryStore::new());
+//! ```
+
+use crate::request::Cookies;
+use crate::stream::Streaming;
+use axum::{
+    body::Body,
+    http::{HeaderValue, Request, Response, header},
+};
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use colored::Colorize;
+use cookie::{Cookie, SameSite};
+use futures::future::{self, BoxFuture};
+use futures::{StreamExt, stream};
+use rand::RngCore;
+use serde::{Serialize, de::DeserializeOwned};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, SystemTime};
+use tower::{Layer, Service};
+
+/// The values stored in a session.
+pub type SessionData = HashMap<String, serde_json::Value>;
+
+/// Error type returned by session stores.
+pub type StoreError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Backend that persists session data.
+///
+/// Implement this to keep sessions somewhere other than the built-in
+/// [`MemoryStore`] or `SqliteStore`.
+pub trait SessionStore: Send + Sync + 'static {
+    /// Load a session, returning `None` if it doesn't exist or has expired.
+    fn load<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<Option<SessionData>, StoreError>>;
+
+    /// Create or replace a session.
+    fn save<'a>(
+        &'a self,
+        id: &'a str,
+        data: &'a SessionData,
+        expires_at: SystemTime,
+    ) -> BoxFuture<'a, Result<(), StoreError>>;
+
+    /// Delete a session.
+    fn delete<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<(), StoreError>>;
+}
+
+/// Session cookie and expiry settings.
+#[derive(Debug, Clone)]
+pub struct SessionConfig {
+    cookie_name: String,
+    ttl: Duration,
+    secure: bool,
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        Self {
+            cookie_name: "rejoice_session".to_string(),
+            ttl: Duration::from_secs(60 * 60 * 24 * 7),
+            secure: false,
+        }
+    }
+}
+
+impl SessionConfig {
+    /// Name of the cookie holding the session ID (default `rejoice_session`).
+    pub fn cookie_name(mut self, name: impl Into<String>) -> Self {
+        self.cookie_name = name.into();
+        self
+    }
+
+    /// How long a session lives after it was last saved (default 7 days).
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Only send the session cookie over HTTPS (default `false`).
+    pub fn secure(mut self, secure: bool) -> Self {
+        self.secure = secure;
+        self
+    }
+}
+
+/// A handle to the current request's session.
+///
+/// Get it with `req.session()`. Changes are saved after the handler returns,
+/// and only if something actually changed.
+///
+/// # Example
+/// ```ignore
+/// pub async fn post(req: Req, res: Res) -> Res {
+///     let session = req.session();
+///     let visits: u32 = session.get("visits").unwrap_or(0);
+///     session.insert("visits", visits + 1);
+///     res.redirect("/")
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Session {
+    state: Arc<Mutex<SessionState>>,
+}
+
+#[derive(Debug, Default)]
+struct SessionState {
+    id: Option<String>,
+    data: SessionData,
+    changed: bool,
+    rotate: bool,
+    destroyed: bool,
+}
+
+impl Session {
+    pub(crate) fn new(id: Option<String>, data: SessionData) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(SessionState {
+                id,
+                data,
+                ..Default::default()
+            })),
+        }
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, SessionState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// The session ID, or `None` if this session hasn't been saved yet.
+    pub fn id(&self) -> Option<String> {
+        self.state().id.clone()
+    }
+
+    /// Get a value, returning `None` if it's missing or has a different type.
+    pub fn get<T: DeserializeOwned>(&self, key: &str) -> Option<T> {
+        let value = self.state().data.get(key)?.clone();
+        serde_json::from_value(value).ok()
+    }
+
+    /// Check if a key is set.
+    pub fn contains(&self, key: &str) -> bool {
+        self.state().data.contains_key(key)
+    }
+
+    /// Store a value under `key`, replacing any previous value.
+    pub fn insert<T: Serialize>(&self, key: impl Into<String>, value: T) {
+        let key = key.into();
+        match serde_json::to_value(value) {
+            Ok(value) => {
+                let mut state = self.state();
+                if state.data.get(&key) != Some(&value) {
+                    state.data.insert(key, value);
+                    state.changed = true;
+                }
+            }
+            Err(e) => {
+                eprintln!(
+                    "{} failed to store session value {:?}: {}",
+                    "warning:".yellow().bold(),
+                    key,
+                    e
+                );
+            }
+        }
+    }
+
+    /// Remove a value, returning it if it was set.
+    pub fn remove<T: DeserializeOwned>(&self, key: &str) -> Option<T> {
+        let mut state = self.state();
+        let value = state.data.remove(key)?;
+        state.changed = true;
+        serde_json::from_value(value).ok()
+    }
+
+    /// Remove all values but keep the session.
+    pub fn clear(&self) {
+        let mut state = self.state();
+        if !state.data.is_empty() {
+            state.data.clear();
+            state.changed = true;
+        }
+    }
+
+    /// Give the session a new ID while keeping its data.
+    ///
+    /// Call this when a user logs in to prevent session fixation.
+    pub fn rotate_id(&self) {
+        let mut state = self.state();
+        state.rotate = true;
+        state.changed = true;
+    }
+
+    /// Delete the session from the store and remove the cookie.
+    ///
+    /// Call this when a user logs out. Values inserted afterwards start a new
+    /// session with a fresh ID.
+    pub fn destroy(&self) {
+        let mut state = self.state();
+        state.data.clear();
+        state.destroyed = true;
+    }
+}
+
+/// Generate a random, URL-safe identifier with 256 bits of entropy.
+pub(crate) fn generate_id() -> String {
+    let mut bytes = [0u8; 32];
+    rand::rng().fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+/// In-memory session store.
+///
+/// Sessions are lost when the server restarts, so this is best suited to
+/// development and single-instance apps.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryStore {
+    sessions: Arc<Mutex<HashMap<String, (SessionData, SystemTime)>>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl SessionStore for MemoryStore {
+    fn load<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<Option<SessionData>, StoreError>> {
+        Box::pin(async move {
+            let sessions = self.sessions.lock().unwrap_or_else(|e| e.into_inner());
+            Ok(sessions
+                .get(id)
+                .filter(|(_, expires_at)| *expires_at > SystemTime::now())
+                .map(|(data, _)| data.clone()))
+        })
+    }
+
+    fn save<'a>(
+        &'a self,
+        id: &'a str,
+        data: &'a SessionData,
+        expires_at: SystemTime,
+    ) -> BoxFuture<'a, Result<(), StoreError>> {
+        Box::pin(async move {
+            let mut sessions = self.sessions.lock().unwrap_or_else(|e| e.into_inner());
+            let now = SystemTime::now();
+            sessions.retain(|_, (_, expires_at)| *expires_at > now);
+            sessions.insert(id.to_string(), (data.clone(), expires_at));
+            Ok(())
+        })
+    }
+
+    fn delete<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<(), StoreError>> {
+        Box::pin(async move {
+            let mut sessions = self.sessions.lock().unwrap_or_else(|e| e.into_inner());
+            sessions.remove(id);
+            Ok(())
+        })
+    }
+}
+
+/// SQLite session store using the app's database pool.
+///
+/// Call [`migrate`](Self::migrate) once at startup to create the
+/// `rejoice_sessions` table.
+///
+/// ```ignore
+/// let store = SqliteStore::new(pool.clone());
+/// store.migrate().await.expect("Failed to create sessions table");
+/// let app = App::with_state(8080, create_router(), state).sessions(store);
+/// ```
+#[cfg(feature = "sqlite")]
+#[derive(Debug, Clone)]
+pub struct SqliteStore {
+    pool: sqlx::Pool<sqlx::Sqlite>,
+}
+
+#[cfg(feature = "sqlite")]
+impl SqliteStore {
+    pub fn new(pool: sqlx::Pool<sqlx::Sqlite>) -> Self {
+        Self { pool }
+    }
+
+    /// Create the sessions table if it doesn't exist.
+    pub async fn migrate(&self) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "CREATE TABLE IF NOT EXISTS rejoice_sessions (
+                id TEXT PRIMARY KEY NOT NULL,
+                data TEXT NOT NULL,
+                expires_at INTEGER NOT NULL
+            )",
+        )
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Delete all expired sessions.
+    pub async fn delete_expired(&self) -> Result<u64, sqlx::Error> {
+        let result = sqlx::query("DELETE FROM rejoice_sessions WHERE expires_at <= ?")
+            .bind(unix_seconds(SystemTime::now()))
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected())
+    }
+}
+
+#[cfg(feature = "sqlite")]
+fn unix_seconds(time: SystemTime) -> i64 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+#[cfg(feature = "sqlite")]
+impl SessionStore for SqliteStore {
+    fn load<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<Option<SessionData>, StoreError>> {
+        Box::pin(async move {
+            let data: Option<String> = sqlx::query_scalar(
+                "SELECT data FROM rejoice_sessions WHERE id = ? AND expires_at > ?",
+            )
+            .bind(id)
+            .bind(unix_seconds(SystemTime::now()))
+            .fetch_optional(&self.pool)
+            .await?;
+
+            match data {
+                Some(data) => Ok(Some(serde_json::from_str(&data)?)),
+                None => Ok(None),
+            }
+        })
+    }
+
+    fn save<'a>(
+        &'a self,
+        id: &'a str,
+        data: &'a SessionData,
+        expires_at: SystemTime,
+    ) -> BoxFuture<'a, Result<(), StoreError>> {
+        Box::pin(async move {
+            sqlx::query(
+                "INSERT INTO rejoice_sessions (id, data, expires_at) VALUES (?, ?, ?)
+                 ON CONFLICT(id) DO UPDATE SET data = excluded.data, expires_at = excluded.expires_at",
+            )
+            .bind(id)
+            .bind(serde_json::to_string(data)?)
+            .bind(unix_seconds(expires_at))
+            .execute(&self.pool)
+            .await?;
+            Ok(())
+        })
+    }
+
+    fn delete<'a>(&'a self, id: &'a str) -> BoxFuture<'a, Result<(), StoreError>> {
+        Box::pin(async move {
+            sqlx::query("DELETE FROM rejoice_sessions WHERE id = ?")
+                .bind(id)
+                .execute(&self.pool)
+                .await?;
+            Ok(())
+        })
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct SessionLayer {
+    pub store: Arc<dyn SessionStore>,
+    pub config: Arc<SessionConfig>,
+}
+
+impl<S> Layer<S> for SessionLayer {
+    type Service = SessionMiddleware<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        SessionMiddleware {
+            inner,
+            store: self.store.clone(),
+            config: self.config.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct SessionMiddleware<S> {
+    inner: S,
+    store: Arc<dyn SessionStore>,
+    config: Arc<SessionConfig>,
+}
+
+impl<S> Service<Request<Body>> for SessionMiddleware<S>
+where
+    S: Service<Request<Body>, Response = Response<Body>> + Clone + Send + 'static,
+    S::Future: Send,
+{
+    type Response = Response<Body>;
+    type Error = S::Error;
+    type Future = std::pin::Pin<
+        Box<dyn std::future::Future<Output = Result<Self::Response, Self::Error>> + Send>,
+    >;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request<Body>) -> Self::Future {
+        let mut inner = self.inner.clone();
+        let store = self.store.clone();
+        let config = self.config.clone();
+
+        Box::pin(async move {
+            let cookie_id = Cookies::from_header(
+                req.headers()
+                    .get(header::COOKIE)
+                    .and_then(|v| v.to_str().ok()),
+            )
+            .get(&config.cookie_name)
+            .map(|id| id.to_string());
+
+            // Load the existing session, starting fresh if it's unknown or expired
+            let mut session = Session::new(None, SessionData::new());
+            if let Some(id) = cookie_id {
+                match store.load(&id).await {
+                    Ok(Some(data)) => session = Session::new(Some(id), data),
+                    Ok(None) => {}
+                    Err(e) => log_store_error("load", &e),
+                }
+            }
+
+            req.extensions_mut().insert(session.clone());
+            let mut response = inner.call(req).await?;
+
+            let cookie = persist(&session, store.as_ref(), &config).await;
+            if let Some(value) = cookie.and_then(|c| HeaderValue::from_str(&c).ok()) {
+                response.headers_mut().append(header::SET_COOKIE, value);
+            }
+
+            // Streamed chunks can still change the session, e.g. by rendering
+            // a CSRF token, so save again once the body has been sent
+            if response.extensions().get::<Streaming>().is_some() {
+                let saved = stream::once(async move {
+                    save_streamed(&session, store.as_ref(), &config).await;
+                    None
+                })
+                .filter_map(future::ready);
+                response =
+                    response.map(|body| Body::from_stream(body.into_data_stream().chain(saved)));
+            }
+
+            Ok(response)
+        })
+    }
+}
+
+/// Save or delete the session as needed, returning a `Set-Cookie` value if
+/// the browser's cookie has to change.
+async fn persist(
+    session: &Session,
+    store: &dyn SessionStore,
+    config: &SessionConfig,
+) -> Option<String> {
+    let (old_id, destroyed, rotate, changed, data) = {
+        let mut state = session.state();
+        let destroyed = std::mem::take(&mut state.destroyed);
+        let old_id = if destroyed {
+            state.id.take()
+        } else {
+            state.id.clone()
+        };
+        (
+            old_id,
+            destroyed,
+            state.rotate,
+            state.changed,
+            state.data.clone(),
+        )
+    };
+
+    let mut cookie = Cookie::build((config.cookie_name.clone(), String::new()))
+        .path("/")
+        .http_only(true)
+        .secure(config.secure)
+        .same_site(SameSite::Lax)
+        .build();
+
+    let old_id = if destroyed {
+        if let Some(old_id) = &old_id
+            && let Err(e) = store.delete(old_id).await
+        {
+            log_store_error("delete", &e);
+        }
+        // Values inserted after `destroy` go into a new session
+        if data.is_empty() {
+            old_id?;
+            cookie.make_removal();
+            return Some(cookie.encoded().to_string());
+        }
+        None
+    } else {
+        old_id
+    };
+
+    // Lazy saves: untouched sessions, and new sessions that never received
+    // any data, don't hit the store or set a cookie
+    if !changed || (old_id.is_none() && data.is_empty()) {
+        return None;
+    }
+
+    let id = match &old_id {
+        Some(id) if !rotate => id.clone(),
+        _ => generate_id(),
+    };
+    if rotate
+        && let Some(old_id) = &old_id
+        && let Err(e) = store.delete(old_id).await
+    {
+        log_store_error("delete", &e);
+    }
+
+    if let Err(e) = store.save(&id, &data, SystemTime::now() + config.ttl).await {
+        log_store_error("save", &e);
+        return None;
+    }
+
+    {
+        let mut state = session.state();
+        state.id = Some(id.clone());
+        state.changed = false;
+        state.rotate = false;
+    }
+
+    cookie.set_value(id);
+    cookie.set_max_age(cookie::time::Duration::try_from(config.ttl).ok());
+    Some(cookie.encoded().to_string())
+}
+
+/// Save changes made while a streamed body was sent.
+///
+/// The cookie is already on its way by then, so only sessions that have an
+/// ID can be saved.
+async fn save_streamed(session: &Session, store: &dyn SessionStore, config: &SessionConfig) {
+    let (id, data) = {
+        let state = session.state();
+        match &state.id {
+            Some(id) if state.changed && !state.destroyed => (id.clone(), state.data.clone()),
+            _ => return,
+        }
+    };
+    if let Err(e) = store.save(&id, &data, SystemTime::now() + config.ttl).await {
+        log_store_error("save", &e);
+    }
+}
+
+fn log_store_error(action: &str, error: &StoreError) {
+    eprintln!(
+        "{} failed to {} session: {}",
+        "error:".red().bold(),
+        action,
+        error
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn saved_session(store: &MemoryStore) -> Session {
+        let mut data = SessionData::new();
+        data.insert("user".to_string(), 1.into());
+        let expires_at = SystemTime::now() + Duration::from_secs(60);
+        store.save("old", &data, expires_at).await.unwrap();
+        Session::new(Some("old".to_string()), data)
+    }
+
+    #[tokio::test]
+    async fn destroy_removes_the_session_and_cookie() {
+        let store = MemoryStore::new();
+        let session = saved_session(&store).await;
+        session.destroy();
+
+        let cookie = persist(&session, &store, &SessionConfig::default())
+            .await
+            .unwrap();
+        let cookie = Cookie::parse_encoded(cookie).unwrap();
+        assert_eq!(cookie.value(), "");
+        assert_eq!(cookie.max_age(), Some(cookie::time::Duration::ZERO));
+        assert!(store.load("old").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn values_inserted_after_destroy_start_a_new_session() {
+        let store = MemoryStore::new();
+        let session = saved_session(&store).await;
+        session.destroy();
+        session.insert("notice", "Logged out");
+
+        let cookie = persist(&session, &store, &SessionConfig::default())
+            .await
+            .unwrap();
+        let id = Cookie::parse_encoded(cookie).unwrap().value().to_string();
+        assert_ne!(id, "old");
+        assert!(store.load("old").await.unwrap().is_none());
+
+        let data = store.load(&id).await.unwrap().unwrap();
+        assert_eq!(data.get("notice"), Some(&"Logged out".into()));
+        assert_eq!(data.get("user"), None);
+    }
+}
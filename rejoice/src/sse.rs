@@ -0,0 +1,241 @@
+//! Server-Sent Events.
+//!
+//! Return a stream of [`Event`]s with `Res::sse`, or broadcast events to
+//! every connected client with a [`Hub`].
+//!
+//! ```ignore
+//! // src/routes/notifications.rs
+//! pub async fn get(state: AppState, req: Req, res: Res) -> Res {
+//!     res.sse(state.notifications.subscribe(&req))
+//! }
+//!
+//! // Anywhere else
+//! state.notifications.send(Event::new("Build finished").event("status"));
+//! ```
+
+use crate::Req;
+use axum::BoxError;
+use axum::body::Bytes;
+use futures::stream::{self, BoxStream, Stream, StreamExt};
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::broadcast;
+
+/// How long a connection may be idle before a keep-alive comment is sent.
+///
+/// Proxies and load balancers commonly close connections that are silent
+/// for 30-60 seconds.
+pub const KEEP_ALIVE: Duration = Duration::from_secs(15);
+
+/// A single Server-Sent Event.
+#[derive(Debug, Clone, Default)]
+pub struct Event {
+    event: Option<String>,
+    data: String,
+    id: Option<String>,
+    retry: Option<Duration>,
+}
+
+impl Event {
+    /// Create an event carrying `data`. Multi-line data is sent as several
+    /// `data:` lines and arrives intact in the browser.
+    pub fn new(data: impl Into<String>) -> Self {
+        Self {
+            data: data.into(),
+            ..Self::default()
+        }
+    }
+
+    /// Create an event carrying `value` serialized as JSON.
+    ///
+    /// # Panics
+    /// Panics if serialization fails.
+    pub fn json<T: Serialize>(value: &T) -> Self {
+        Self::new(serde_json::to_string(value).expect("Failed to serialize SSE event"))
+    }
+
+    /// Set the event name, dispatched to `addEventListener(name, ...)` in the
+    /// browser instead of `onmessage`.
+    pub fn event(mut self, name: impl Into<String>) -> Self {
+        self.event = Some(name.into());
+        self
+    }
+
+    /// Set the event ID, which the browser sends back in `Last-Event-ID` when
+    /// it reconnects.
+    pub fn id(mut self, id: impl Into<String>) -> Self {
+        self.id = Some(id.into());
+        self
+    }
+
+    /// Tell the browser how long to wait before reconnecting.
+    pub fn retry(mut self, retry: Duration) -> Self {
+        self.retry = Some(retry);
+        self
+    }
+
+    /// Encode in the `text/event-stream` format.
+    fn encode(&self) -> Bytes {
+        let mut out = String::new();
+        if let Some(event) = &self.event {
+            out.push_str(&format!("event: {}\n", single_line(event)));
+        }
+        if let Some(id) = &self.id {
+            out.push_str(&format!("id: {}\n", single_line(id)));
+        }
+        if let Some(retry) = self.retry {
+            out.push_str(&format!("retry: {}\n", retry.as_millis()));
+        }
+        for line in self.data.split('\n') {
+            out.push_str(&format!("data: {}\n", line.strip_suffix('\r').unwrap_or(line)));
+        }
+        out.push('\n');
+        Bytes::from(out)
+    }
+}
+
+/// Field values other than data can't span lines.
+fn single_line(value: &str) -> String {
+    value.replace(['\r', '\n'], " ")
+}
+
+/// Encode events and interleave keep-alive comments while the stream is idle.
+pub(crate) fn encode<S>(events: S) -> BoxStream<'static, Result<Bytes, BoxError>>
+where
+    S: Stream<Item = Event> + Send + 'static,
+{
+    stream::unfold(events.boxed(), |mut events| async move {
+        match tokio::time::timeout(KEEP_ALIVE, events.next()).await {
+            Ok(Some(event)) => Some((Ok(event.encode()), events)),
+            Ok(None) => None,
+            Err(_) => Some((Ok(Bytes::from_static(b":\n\n")), events)),
+        }
+    })
+    .boxed()
+}
+
+/// Broadcasts events to every subscribed client.
+///
+/// The hub numbers events and remembers the most recent ones, so a client
+/// that reconnects with `Last-Event-ID` gets what it missed. Cloning a hub
+/// is cheap and clones share subscribers, so keep one in your app state.
+#[derive(Clone)]
+pub struct Hub {
+    inner: Arc<HubInner>,
+}
+
+struct HubInner {
+    sender: broadcast::Sender<(u64, Event)>,
+    state: Mutex<HubState>,
+    capacity: usize,
+}
+
+struct HubState {
+    next_id: u64,
+    recent: VecDeque<(u64, Event)>,
+}
+
+impl Hub {
+    /// Create a hub that remembers the last 100 events.
+    pub fn new() -> Self {
+        Self::with_capacity(100)
+    }
+
+    /// Create a hub that remembers the last `capacity` events for clients
+    /// that reconnect.
+    ///
+    /// # Panics
+    /// Panics if `capacity` is zero.
+    pub fn with_capacity(capacity: usize) -> Self {
+        assert!(capacity > 0, "Hub capacity must be at least 1");
+        Self {
+            inner: Arc::new(HubInner {
+                sender: broadcast::channel(capacity).0,
+                state: Mutex::new(HubState {
+                    next_id: 1,
+                    recent: VecDeque::with_capacity(capacity),
+                }),
+                capacity,
+            }),
+        }
+    }
+
+    /// Send an event to every subscriber.
+    ///
+    /// The hub assigns the event's ID, replacing any set with [`Event::id`].
+    pub fn send(&self, event: Event) {
+        let mut state = self.inner.state.lock().unwrap_or_else(|e| e.into_inner());
+        let id = state.next_id;
+        state.next_id += 1;
+
+        let event = event.id(id.to_string());
+        if state.recent.len() == self.inner.capacity {
+            state.recent.pop_front();
+        }
+        state.recent.push_back((id, event.clone()));
+
+        // Sending fails only when nobody is subscribed
+        let _ = self.inner.sender.send((id, event));
+    }
+
+    /// Subscribe to events, replaying any the client missed according to
+    /// the request's `Last-Event-ID` header.
+    ///
+    /// Pass the result to `Res::sse`.
+    pub fn subscribe(&self, req: &Req) -> impl Stream<Item = Event> + Send + 'static {
+        let last_id = req
+            .headers
+            .get("last-event-id")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.trim().parse::<u64>().ok());
+
+        // Subscribe while holding the lock so no event is both replayed and
+        // received live, or missed between the two
+        let state = self.inner.state.lock().unwrap_or_else(|e| e.into_inner());
+        let receiver = self.inner.sender.subscribe();
+        let missed: Vec<Event> = match last_id {
+            Some(last_id) => state
+                .recent
+                .iter()
+                .filter(|(id, _)| *id > last_id)
+                .map(|(_, event)| event.clone())
+                .collect(),
+            None => Vec::new(),
+        };
+        drop(state);
+
+        let live = stream::unfold(receiver, |mut receiver| async move {
+            loop {
+                match receiver.recv().await {
+                    Ok((_, event)) => return Some((event, receiver)),
+                    // A slow client skips what it couldn't keep up with
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+        });
+
+        stream::iter(missed).chain(live)
+    }
+
+    /// Number of currently connected subscribers.
+    pub fn subscriber_count(&self) -> usize {
+        self.inner.sender.receiver_count()
+    }
+}
+
+impl Default for Hub {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl std::fmt::Debug for Hub {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Hub")
+            .field("subscribers", &self.subscriber_count())
+            .finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,238 @@
+//! Streaming response bodies.
+
+use axum::body::{Body, Bytes};
+use axum::BoxError;
+use futures::stream::{self, BoxStream, Stream, StreamExt};
+use maud::Markup;
+use std::sync::{Arc, Mutex};
+
+/// Placeholder passed to layouts as `children` when the page streams.
+///
+/// The layout's output is split around it, so the layout's shell is sent
+/// before the page content and the rest after it.
+pub(crate) const SLOT: &str = "<!--rejoice-stream-slot-->";
+
+/// Response extension marking a streamed body, so middleware doesn't buffer it.
+#[derive(Clone, Copy)]
+pub(crate) struct Streaming;
+
+pub(crate) type ChunkStream = BoxStream<'static, Result<Bytes, BoxError>>;
+
+/// A chunk of a streamed response.
+///
+/// `Markup` chunks make an HTML response that goes through layouts.
+/// Wrap chunks in `Result` to end the stream early on error.
+pub trait IntoChunk: Send + 'static {
+    /// Content type of a response made of these chunks.
+    const CONTENT_TYPE: &'static str;
+
+    /// Convert into the bytes to send.
+    fn into_chunk(self) -> Result<Bytes, BoxError>;
+}
+
+impl IntoChunk for Markup {
+    const CONTENT_TYPE: &'static str = "text/html; charset=utf-8";
+
+    fn into_chunk(self) -> Result<Bytes, BoxError> {
+        Ok(Bytes::from(self.into_string()))
+    }
+}
+
+impl IntoChunk for String {
+    const CONTENT_TYPE: &'static str = "text/plain; charset=utf-8";
+
+    fn into_chunk(self) -> Result<Bytes, BoxError> {
+        Ok(Bytes::from(self))
+    }
+}
+
+impl IntoChunk for &'static str {
+    const CONTENT_TYPE: &'static str = "text/plain; charset=utf-8";
+
+    fn into_chunk(self) -> Result<Bytes, BoxError> {
+        Ok(Bytes::from_static(self.as_bytes()))
+    }
+}
+
+impl IntoChunk for Bytes {
+    const CONTENT_TYPE: &'static str = "application/octet-stream";
+
+    fn into_chunk(self) -> Result<Bytes, BoxError> {
+        Ok(self)
+    }
+}
+
+impl IntoChunk for Vec<u8> {
+    const CONTENT_TYPE: &'static str = "application/octet-stream";
+
+    fn into_chunk(self) -> Result<Bytes, BoxError> {
+        Ok(Bytes::from(self))
+    }
+}
+
+impl<C, E> IntoChunk for Result<C, E>
+where
+    C: IntoChunk,
+    E: Into<BoxError> + Send + 'static,
+{
+    const CONTENT_TYPE: &'static str = C::CONTENT_TYPE;
+
+    fn into_chunk(self) -> Result<Bytes, BoxError> {
+        self.map_err(Into::into).and_then(IntoChunk::into_chunk)
+    }
+}
+
+/// A streamed body, shared between clones of a `Res`.
+///
+/// Whichever clone is turned into a response first takes the stream.
+#[derive(Clone)]
+pub(crate) struct StreamBody {
+    pub content_type: &'static str,
+    stream: Arc<Mutex<Option<ChunkStream>>>,
+}
+
+impl std::fmt::Debug for StreamBody {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("StreamBody")
+            .field("content_type", &self.content_type)
+            .finish_non_exhaustive()
+    }
+}
+
+impl StreamBody {
+    pub fn new<S, C>(chunks: S) -> Self
+    where
+        S: Stream<Item = C> + Send + 'static,
+        C: IntoChunk,
+    {
+        Self::from_stream(C::CONTENT_TYPE, chunks.map(IntoChunk::into_chunk).boxed())
+    }
+
+    pub fn from_stream(content_type: &'static str, stream: ChunkStream) -> Self {
+        Self {
+            content_type,
+            stream: Arc::new(Mutex::new(Some(stream))),
+        }
+    }
+
+    pub fn is_html(&self) -> bool {
+        self.content_type.starts_with("text/html")
+    }
+
+    fn take(&self) -> ChunkStream {
+        self.stream
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .take()
+            .unwrap_or_else(|| stream::empty().boxed())
+    }
+
+    /// Surround the stream with a layout's output, split at [`SLOT`].
+    ///
+    /// Returns `None` if the layout doesn't render its children.
+    pub fn wrap(&self, layout_html: &str) -> Option<Self> {
+        let (prefix, suffix) = layout_html.split_once(SLOT)?;
+        let prefix = stream::once(futures::future::ready(Ok(Bytes::from(prefix.to_string()))));
+        let suffix = stream::once(futures::future::ready(Ok(Bytes::from(suffix.to_string()))));
+        let wrapped = prefix.chain(self.take()).chain(suffix).boxed();
+        Some(Self::from_stream(self.content_type, wrapped))
+    }
+
+    pub fn into_body(self) -> Body {
+        Body::from_stream(self.take())
+    }
+}
+
+/// Injects markup into an HTML body as it streams.
+///
+/// `head` goes before `</head>` and `tail` before `</body>`. Output is held
+/// back until `</head>` is seen, so `head` can still be prepended if there's
+/// none; after that, only a trailing partial `</body>` is held.
+pub(crate) struct Injector {
+    head: Option<String>,
+    tail: Option<String>,
+    buf: Vec<u8>,
+}
+
+impl Injector {
+    pub fn new(head: String, tail: String) -> Self {
+        Self {
+            head: (!head.is_empty()).then_some(head),
+            tail: (!tail.is_empty()).then_some(tail),
+            buf: Vec::new(),
+        }
+    }
+
+    /// Inject into a streamed body.
+    pub fn wrap(self, body: Body) -> Body {
+        let chunks = stream::unfold(
+            (body.into_data_stream(), Some(self)),
+            |(mut body, injector)| async move {
+                let mut injector = injector?;
+                loop {
+                    match body.next().await {
+                        Some(Ok(chunk)) => {
+                            let out = injector.push(&chunk);
+                            if !out.is_empty() {
+                                return Some((Ok(out), (body, Some(injector))));
+                            }
+                        }
+                        Some(Err(e)) => return Some((Err(BoxError::from(e)), (body, None))),
+                        None => return Some((Ok(injector.finish()), (body, None))),
+                    }
+                }
+            },
+        );
+        Body::from_stream(chunks)
+    }
+
+    fn push(&mut self, chunk: &[u8]) -> Bytes {
+        self.buf.extend_from_slice(chunk);
+
+        if let Some(head) = &self.head {
+            let Some(at) = find(&self.buf, b"</head>") else {
+                return Bytes::new();
+            };
+            self.buf.splice(at..at, head.bytes());
+            self.head = None;
+        }
+
+        if let Some(tail) = &self.tail {
+            if let Some(at) = find(&self.buf, b"</body>") {
+                self.buf.splice(at..at, tail.bytes());
+                self.tail = None;
+            } else {
+                // Hold back a trailing partial `</body>` that the next chunk may
+                // complete
+                let keep = (1..b"</body>".len())
+                    .rev()
+                    .find(|&n| self.buf.ends_with(&b"</body>"[..n]))
+                    .unwrap_or(0);
+                let rest = self.buf.split_off(self.buf.len() - keep);
+                return Bytes::from(std::mem::replace(&mut self.buf, rest));
+            }
+        }
+
+        Bytes::from(std::mem::take(&mut self.buf))
+    }
+
+    fn finish(self) -> Bytes {
+        let mut out = self.buf;
+        if let Some(head) = self.head {
+            out.splice(0..0, head.bytes());
+        }
+        if let Some(tail) = self.tail {
+            match find(&out, b"</body>") {
+                Some(at) => {
+                    out.splice(at..at, tail.bytes());
+                }
+                None => out.extend_from_slice(tail.as_bytes()),
+            }
+        }
+        Bytes::from(out)
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
@@ -0,0 +1,308 @@
+//! HTTPS listener for `App::tls`.
+//!
+//! Certificates are read from PEM files and reloaded whenever the files
+//! change, so renewals (e.g. by certbot) take effect without a restart.
+
+use axum::{
+    Router,
+    extract::Request,
+    http::{StatusCode, Uri, header},
+    response::{IntoResponse, Response},
+    serve::Listener,
+};
+use colored::Colorize;
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use rustls::ServerConfig;
+use rustls::crypto::CryptoProvider;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, pem::PemObject};
+use rustls::server::{ClientHello, ResolvesServerCert};
+use rustls::sign::CertifiedKey;
+use std::io;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock, mpsc};
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinSet;
+use tokio_rustls::TlsAcceptor;
+use tokio_rustls::server::TlsStream;
+
+/// How long a client gets to complete the TLS handshake.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long the certificate files must stay unchanged before reloading.
+const RELOAD_DELAY: Duration = Duration::from_millis(500);
+
+/// Certificate and key files configured with `App::tls`.
+pub(crate) struct TlsConfig {
+    pub cert: PathBuf,
+    pub key: PathBuf,
+}
+
+fn provider() -> Arc<CryptoProvider> {
+    Arc::new(rustls::crypto::ring::default_provider())
+}
+
+/// Read a certificate chain and private key from PEM files.
+fn load(cert: &Path, key: &Path, provider: &CryptoProvider) -> io::Result<CertifiedKey> {
+    let invalid = |path: &Path, error: &dyn std::fmt::Display| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("could not read {}: {}", path.display(), error),
+        )
+    };
+
+    let chain = CertificateDer::pem_file_iter(cert)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| invalid(cert, &e))?;
+    if chain.is_empty() {
+        return Err(invalid(cert, &"no certificates found"));
+    }
+    let private_key = PrivateKeyDer::from_pem_file(key).map_err(|e| invalid(key, &e))?;
+
+    CertifiedKey::from_der(chain, private_key, provider).map_err(|e| invalid(key, &e))
+}
+
+/// Hands out the current certificate, which the watcher replaces on change.
+#[derive(Debug)]
+struct Resolver(RwLock<Arc<CertifiedKey>>);
+
+impl ResolvesServerCert for Resolver {
+    fn resolve(&self, _client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        Some(self.0.read().unwrap_or_else(|e| e.into_inner()).clone())
+    }
+}
+
+/// Reload the certificate when either file changes.
+///
+/// The parent directories are watched rather than the files, since
+/// renewals often replace the files (or the symlinks pointing to them).
+fn watch(
+    config: &TlsConfig,
+    resolver: Arc<Resolver>,
+    provider: Arc<CryptoProvider>,
+) -> notify::Result<RecommendedWatcher> {
+    let names = [&config.cert, &config.key].map(|path| path.file_name().map(ToOwned::to_owned));
+    let (changed_tx, changed_rx) = mpsc::channel();
+
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        let Ok(event) = event else { return };
+        let relevant = (event.kind.is_create() || event.kind.is_modify())
+            && event.paths.iter().any(|path| {
+                names
+                    .iter()
+                    .flatten()
+                    .any(|name| path.file_name() == Some(name))
+            });
+        if relevant {
+            let _ = changed_tx.send(());
+        }
+    })?;
+
+    for path in [&config.cert, &config.key] {
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        watcher.watch(dir, RecursiveMode::NonRecursive)?;
+    }
+
+    // Stops once the watcher, and with it the sender, is dropped
+    let cert = config.cert.clone();
+    let key = config.key.clone();
+    std::thread::spawn(move || {
+        while changed_rx.recv().is_ok() {
+            // Renewals write the certificate and key separately, so wait
+            // for the changes to settle before loading
+            while changed_rx.recv_timeout(RELOAD_DELAY).is_ok() {}
+
+            match load(&cert, &key, &provider) {
+                Ok(certified) => {
+                    *resolver.0.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(certified);
+                    println!("{} reloaded TLS certificate", "✓".green().bold());
+                }
+                Err(error) => eprintln!(
+                    "{} keeping the current TLS certificate: {}",
+                    "warning:".yellow().bold(),
+                    error
+                ),
+            }
+        }
+    });
+
+    Ok(watcher)
+}
+
+/// TCP listener that completes the TLS handshake before handing out
+/// connections.
+pub(crate) struct TlsListener {
+    tcp: TcpListener,
+    acceptor: TlsAcceptor,
+    /// Handshakes in progress, run concurrently so a slow client can't hold
+    /// up others
+    handshakes: JoinSet<Option<(TlsStream<TcpStream>, SocketAddr)>>,
+    _watcher: Option<RecommendedWatcher>,
+}
+
+impl TlsListener {
+    /// Load the certificate and wrap `tcp`.
+    pub fn new(tcp: TcpListener, config: &TlsConfig) -> io::Result<Self> {
+        let provider = provider();
+        let certified = load(&config.cert, &config.key, &provider)?;
+        let resolver = Arc::new(Resolver(RwLock::new(Arc::new(certified))));
+
+        let mut server_config = ServerConfig::builder_with_provider(provider.clone())
+            .with_safe_default_protocol_versions()
+            .map_err(io::Error::other)?
+            .with_no_client_auth()
+            .with_cert_resolver(resolver.clone());
+        server_config.alpn_protocols = vec![b"http/1.1".to_vec()];
+
+        let watcher = watch(config, resolver, provider)
+            .inspect_err(|error| {
+                eprintln!(
+                    "{} TLS certificates won't reload on change: {}",
+                    "warning:".yellow().bold(),
+                    error
+                )
+            })
+            .ok();
+
+        Ok(Self {
+            tcp,
+            acceptor: TlsAcceptor::from(Arc::new(server_config)),
+            handshakes: JoinSet::new(),
+            _watcher: watcher,
+        })
+    }
+}
+
+impl Listener for TlsListener {
+    type Io = TlsStream<TcpStream>;
+    type Addr = SocketAddr;
+
+    async fn accept(&mut self) -> (Self::Io, Self::Addr) {
+        loop {
+            tokio::select! {
+                (stream, addr) = Listener::accept(&mut self.tcp) => {
+                    let acceptor = self.acceptor.clone();
+                    self.handshakes.spawn(async move {
+                        let stream = tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream))
+                            .await
+                            .ok()?
+                            .ok()?;
+                        Some((stream, addr))
+                    });
+                }
+                Some(handshake) = self.handshakes.join_next() => {
+                    // Failed handshakes are dropped; keep waiting for the next
+                    if let Ok(Some(connection)) = handshake {
+                        return connection;
+                    }
+                }
+            }
+        }
+    }
+
+    fn local_addr(&self) -> io::Result<Self::Addr> {
+        self.tcp.local_addr()
+    }
+}
+
+/// Router that sends every plain HTTP request to the same URL over HTTPS
+/// on `https_port`.
+pub(crate) fn redirect_router(https_port: u16) -> Router {
+    Router::new().fallback(move |req: Request| async move { redirect_to_https(&req, https_port) })
+}
+
+fn redirect_to_https(req: &Request, https_port: u16) -> Response {
+    let Some(host) = req
+        .headers()
+        .get(header::HOST)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|host| host.parse::<axum::http::uri::Authority>().ok())
+    else {
+        return (StatusCode::BAD_REQUEST, "Missing Host header").into_response();
+    };
+
+    // `host()` keeps the brackets around IPv6 addresses
+    let authority = match https_port {
+        443 => host.host().to_string(),
+        port => format!("{}:{}", host.host(), port),
+    };
+    let path = req
+        .uri()
+        .path_and_query()
+        .map(|p| p.as_str())
+        .unwrap_or("/");
+
+    match Uri::builder()
+        .scheme("https")
+        .authority(authority)
+        .path_and_query(path)
+        .build()
+    {
+        Ok(uri) => (
+            StatusCode::PERMANENT_REDIRECT,
+            [(header::LOCATION, uri.to_string())],
+        )
+            .into_response(),
+        Err(_) => (StatusCode::BAD_REQUEST, "Invalid Host header").into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustls::{ClientConfig, RootCertStore, pki_types::ServerName};
+    use tokio::io::AsyncWriteExt;
+    use tokio_rustls::TlsConnector;
+
+    fn fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures")
+            .join(name)
+    }
+
+    #[tokio::test]
+    async fn failed_handshake_does_not_stall_later_ones() {
+        let config = TlsConfig {
+            cert: fixture("localhost-cert.pem"),
+            key: fixture("localhost-key.pem"),
+        };
+        let tcp = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = tcp.local_addr().unwrap();
+        let mut listener = TlsListener::new(tcp, &config).unwrap();
+
+        let mut roots = RootCertStore::empty();
+        for cert in CertificateDer::pem_file_iter(&config.cert).unwrap() {
+            roots.add(cert.unwrap()).unwrap();
+        }
+        let client_config = ClientConfig::builder_with_provider(provider())
+            .with_safe_default_protocol_versions()
+            .unwrap()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        let connector = TlsConnector::from(Arc::new(client_config));
+
+        // Both connections are accepted first, then the bad handshake fails
+        // while the good one is still in progress
+        let clients = tokio::spawn(async move {
+            let mut bad = TcpStream::connect(addr).await.unwrap();
+            let good = TcpStream::connect(addr).await.unwrap();
+            tokio::time::sleep(Duration::from_millis(100)).await;
+
+            // Plain HTTP to the TLS port fails the handshake
+            bad.write_all(b"GET / HTTP/1.1\r\n\r\n").await.unwrap();
+            bad.shutdown().await.unwrap();
+            tokio::time::sleep(Duration::from_millis(100)).await;
+
+            let name = ServerName::try_from("localhost").unwrap();
+            connector.connect(name, good).await.unwrap()
+        });
+
+        let accepted = tokio::time::timeout(Duration::from_secs(5), listener.accept()).await;
+        assert!(accepted.is_ok(), "the good handshake was never handed out");
+        clients.await.unwrap();
+    }
+}
@@ -0,0 +1,219 @@
+//! WebSocket endpoints in route files.
+//!
+//! A route file that exports `ws` accepts WebSocket connections on its URL.
+//! Ordinary GET requests still reach the file's `get` handler, if it has one.
+//!
+//! ```ignore
+//! // src/routes/echo.rs
+//! use rejoice::{Req, WebSocket};
+//!
+//! pub async fn ws(mut socket: WebSocket, req: Req) {
+//!     while let Some(message) = socket.recv().await {
+//!         if socket.send(message).await.is_err() {
+//!             break;
+//!         }
+//!     }
+//! }
+//! ```
+//!
+//! Stateful apps take the state first, and dynamic routes take the path
+//! parameter last, as with other handlers:
+//! `pub async fn ws(state: AppState, socket: WebSocket, req: Req, id: String)`.
+
+use axum::extract::{FromRequestParts, ws};
+use axum::http::request::Parts;
+use colored::Colorize;
+use futures::stream::{SplitSink, SplitStream};
+use futures::{SinkExt, StreamExt};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+/// A message received from or sent to a WebSocket client.
+///
+/// Pings, pongs and close frames are handled for you.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Message {
+    Text(String),
+    Binary(Vec<u8>),
+}
+
+impl Message {
+    /// The message as text, if it is a text message.
+    pub fn as_text(&self) -> Option<&str> {
+        match self {
+            Message::Text(text) => Some(text),
+            Message::Binary(_) => None,
+        }
+    }
+
+    fn into_axum(self) -> ws::Message {
+        match self {
+            Message::Text(text) => ws::Message::Text(text.into()),
+            Message::Binary(bytes) => ws::Message::Binary(bytes.into()),
+        }
+    }
+}
+
+impl From<String> for Message {
+    fn from(text: String) -> Self {
+        Message::Text(text)
+    }
+}
+
+impl From<&str> for Message {
+    fn from(text: &str) -> Self {
+        Message::Text(text.to_string())
+    }
+}
+
+impl From<Vec<u8>> for Message {
+    fn from(bytes: Vec<u8>) -> Self {
+        Message::Binary(bytes)
+    }
+}
+
+/// A WebSocket connection, passed to a route file's `ws` handler.
+pub struct WebSocket {
+    sender: WebSocketSender,
+    receiver: WebSocketReceiver,
+}
+
+impl WebSocket {
+    #[doc(hidden)]
+    pub fn new(socket: ws::WebSocket) -> Self {
+        let (sender, receiver) = socket.split();
+        Self {
+            sender: WebSocketSender { inner: sender },
+            receiver: WebSocketReceiver { inner: receiver },
+        }
+    }
+
+    /// Wait for the next message. Returns `None` once the connection closes.
+    pub async fn recv(&mut self) -> Option<Message> {
+        self.receiver.recv().await
+    }
+
+    /// Wait for the next message and parse it as JSON. Returns `None` once
+    /// the connection closes.
+    pub async fn recv_json<T: DeserializeOwned>(&mut self) -> Option<Result<T, serde_json::Error>> {
+        self.receiver.recv_json().await
+    }
+
+    /// Send a message.
+    pub async fn send(&mut self, message: impl Into<Message>) -> Result<(), crate::Error> {
+        self.sender.send(message).await
+    }
+
+    /// Send `value` serialized as JSON in a text message.
+    pub async fn send_json<T: Serialize>(&mut self, value: &T) -> Result<(), crate::Error> {
+        self.sender.send_json(value).await
+    }
+
+    /// Close the connection.
+    pub async fn close(self) -> Result<(), crate::Error> {
+        self.sender.close().await
+    }
+
+    /// Split into halves that can be used from separate tasks, e.g. to
+    /// forward broadcast messages while reading from the client.
+    pub fn split(self) -> (WebSocketSender, WebSocketReceiver) {
+        (self.sender, self.receiver)
+    }
+}
+
+/// The sending half of a [`WebSocket`].
+pub struct WebSocketSender {
+    inner: SplitSink<ws::WebSocket, ws::Message>,
+}
+
+impl WebSocketSender {
+    /// Send a message.
+    pub async fn send(&mut self, message: impl Into<Message>) -> Result<(), crate::Error> {
+        Ok(self.inner.send(message.into().into_axum()).await?)
+    }
+
+    /// Send `value` serialized as JSON in a text message.
+    pub async fn send_json<T: Serialize>(&mut self, value: &T) -> Result<(), crate::Error> {
+        self.send(serde_json::to_string(value)?).await
+    }
+
+    /// Close the connection.
+    pub async fn close(mut self) -> Result<(), crate::Error> {
+        Ok(self.inner.close().await?)
+    }
+}
+
+/// The receiving half of a [`WebSocket`].
+pub struct WebSocketReceiver {
+    inner: SplitStream<ws::WebSocket>,
+}
+
+impl WebSocketReceiver {
+    /// Wait for the next message. Returns `None` once the connection closes.
+    pub async fn recv(&mut self) -> Option<Message> {
+        loop {
+            match self.inner.next().await? {
+                Ok(ws::Message::Text(text)) => return Some(Message::Text(text.to_string())),
+                Ok(ws::Message::Binary(bytes)) => return Some(Message::Binary(bytes.to_vec())),
+                // Pongs are sent automatically
+                Ok(ws::Message::Ping(_) | ws::Message::Pong(_)) => continue,
+                Ok(ws::Message::Close(_)) | Err(_) => return None,
+            }
+        }
+    }
+
+    /// Wait for the next message and parse it as JSON. Returns `None` once
+    /// the connection closes.
+    pub async fn recv_json<T: DeserializeOwned>(&mut self) -> Option<Result<T, serde_json::Error>> {
+        Some(match self.recv().await? {
+            Message::Text(text) => serde_json::from_str(&text),
+            Message::Binary(bytes) => serde_json::from_slice(&bytes),
+        })
+    }
+}
+
+/// Extracts a WebSocket upgrade if the request asks for one (used by
+/// generated route wrappers).
+#[doc(hidden)]
+pub struct Upgrade(Option<ws::WebSocketUpgrade>);
+
+impl Upgrade {
+    pub fn into_inner(self) -> Option<ws::WebSocketUpgrade> {
+        self.0
+    }
+}
+
+impl<S> FromRequestParts<S> for Upgrade
+where
+    S: Send + Sync,
+{
+    type Rejection = std::convert::Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        Ok(Upgrade(
+            ws::WebSocketUpgrade::from_request_parts(parts, state)
+                .await
+                .ok(),
+        ))
+    }
+}
+
+/// Return types of `ws` handlers: `()` or `Result<(), E>`.
+///
+/// Errors are logged, since the connection has no response to put them in.
+#[doc(hidden)]
+pub trait HandlerOutcome {
+    fn finish(self);
+}
+
+impl HandlerOutcome for () {
+    fn finish(self) {}
+}
+
+impl<E: std::fmt::Display> HandlerOutcome for Result<(), E> {
+    fn finish(self) {
+        if let Err(e) = self {
+            eprintln!("{} WebSocket handler failed: {}", "error:".red().bold(), e);
+        }
+    }
+}